@@ -0,0 +1,185 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::mesh_functions
+
+// The sea floor with its caustics, see src/water/seabed.rs
+
+// wave time, wrapped to the 96π s the caustics take to come round
+@group(1) @binding(0)
+var<uniform> time: f32;
+// 0 above water up to 1 below
+@group(1) @binding(1)
+var<uniform> amount: f32;
+
+// Must match src/water/shore.rs
+let MAX_SHOALS: u32 = 4u;
+
+struct Shoal {
+    // xy: center, z: radius, w: slope
+    shape: vec4<f32>,
+    depth: f32,
+};
+
+struct Shore {
+    count: u32,
+    // m the water stands above the mean level
+    tide: f32,
+    shoals: array<Shoal, 4>,
+};
+
+@group(1) @binding(2)
+var<uniform> shore: Shore;
+@group(1) @binding(3)
+var rock_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var rock_sampler: sampler;
+
+// Same as in water.wgsl, keep both in sync.
+let OCEAN_DEPTH: f32 = 120.0;
+let SHELF_DROP: f32 = 400.0;
+let BEACH_HEIGHT: f32 = 0.5;
+let REEF_CELL: f32 = 12.0;
+let REEF_DEPTH: f32 = 12.0;
+let REEF_HEIGHT: f32 = 0.6;
+let ROCK_TILE: f32 = 4.0;
+let SAND_COLOR: vec3<f32> = vec3<f32>(0.76, 0.7, 0.5);
+// light lost per m of water, red goes first
+let EXTINCTION: vec3<f32> = vec3<f32>(0.45, 0.09, 0.07);
+
+// m the caustics fade out over, the waves focus the light less the deeper it goes
+let CAUSTICS_DEPTH: f32 = 8.0;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+};
+
+struct FragmentInput {
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+};
+
+// Same as in water.wgsl, keep both in sync.
+fn nearest_shoal(position: vec2<f32>) -> Shoal {
+    var nearest = shore.shoals[0];
+    for (var i: u32 = 1u; i < min(shore.count, MAX_SHOALS); i = i + 1u) {
+        let shoal = shore.shoals[i];
+        let reach = shoal.shape.z + shoal.depth / shoal.shape.w;
+        let nearest_reach = nearest.shape.z + nearest.depth / nearest.shape.w;
+        if (distance(position, shoal.shape.xy) - reach < distance(position, nearest.shape.xy) - nearest_reach) {
+            nearest = shoal;
+        }
+    }
+    return nearest;
+}
+
+fn reef_hash(cell: vec2<f32>) -> f32 {
+    var q = fract(cell * vec2<f32>(0.1031, 0.1030));
+    q = q + dot(q, q.yx + 33.33);
+    return fract((q.x + q.y) * q.x);
+}
+
+// Smooth noise, one blob every `REEF_CELL` at most.
+fn reef_noise(position: vec2<f32>) -> f32 {
+    let p = position / REEF_CELL;
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    return mix(
+        mix(reef_hash(i), reef_hash(i + vec2<f32>(1.0, 0.0)), u.x),
+        mix(reef_hash(i + vec2<f32>(0.0, 1.0)), reef_hash(i + vec2<f32>(1.0, 1.0)), u.x),
+        u.y,
+    );
+}
+
+// How much of the floor at `position` a reef head covers, at `depth` without it.
+fn reef(position: vec2<f32>, depth: f32) -> f32 {
+    let shallows = step(0.0, depth) * (1.0 - smoothstep(REEF_DEPTH * 0.5, REEF_DEPTH, depth));
+    return smoothstep(0.55, 0.8, reef_noise(position)) * shallows;
+}
+
+// The floor below the mean level without reefs: the slope the waves shoal on around the nearest island, up
+// to a low beach, and past its reach a shelf dropping off into the deep ocean.
+fn shelf_depth(position: vec2<f32>) -> f32 {
+    if (min(shore.count, MAX_SHOALS) == 0u) {
+        return OCEAN_DEPTH;
+    }
+    let shoal = nearest_shoal(position);
+    let radius = shoal.shape.z;
+    let reach = radius + shoal.depth / shoal.shape.w;
+    let from_center = distance(position, shoal.shape.xy);
+    let slope = clamp((from_center - radius) * shoal.shape.w, -BEACH_HEIGHT, shoal.depth);
+    return mix(slope, OCEAN_DEPTH, smoothstep(reach, reach + SHELF_DROP, from_center));
+}
+
+// m of water over the floor at `position` at the current tide, negative on land.
+fn seabed_depth(position: vec2<f32>) -> f32 {
+    let depth = shelf_depth(position);
+    return depth * (1.0 - REEF_HEIGHT * reef(position, depth)) + shore.tide;
+}
+
+// Sand with ripples, and the rocks of the reefs. `rock` is the rock texture at `position`.
+fn seabed_color(position: vec2<f32>, rock: vec3<f32>) -> vec3<f32> {
+    let ripples = 0.9 + 0.1 * sin(dot(position, vec2<f32>(1.7, 0.6)) * 3.0);
+    return mix(SAND_COLOR * ripples, rock, reef(position, shelf_depth(position)));
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let world = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    let level = mesh.model[3].y;
+    let e = 0.5;
+    let depth = seabed_depth(world.xz);
+    let dx = seabed_depth(world.xz + vec2<f32>(e, 0.0)) - depth;
+    let dz = seabed_depth(world.xz + vec2<f32>(0.0, e)) - depth;
+
+    var out: VertexOutput;
+    out.world_position = vec4<f32>(world.x, level - depth, world.z, 1.0);
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.world_normal = normalize(vec3<f32>(dx, e, dz));
+    return out;
+}
+
+// Light focused by the waves above into a net of bright lines, a few warped sine layers
+// that tile every 8m.
+fn caustics(position: vec2<f32>, time: f32) -> f32 {
+    let p = fract(position / 8.0) * 6.2831853 - 250.0;
+    var i = p;
+    var c = 1.0;
+    let intensity = 0.005;
+    for (var n: i32 = 0; n < 4; n = n + 1) {
+        let t = time * (1.0 - 3.5 / f32(n + 1));
+        i = p + vec2<f32>(cos(t - i.x) + sin(t + i.y), sin(t - i.y) + cos(t + i.x));
+        c = c + 1.0 / length(vec2<f32>(p.x / (sin(i.x + t) / intensity), p.y / (cos(i.y + t) / intensity)));
+    }
+    c = 1.17 - pow(c / 4.0, 1.4);
+    return clamp(pow(abs(c), 8.0), 0.0, 1.0);
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let position = in.world_position.xz;
+    let N = normalize(in.world_normal);
+    // without mips the texture needs no derivatives, the seams of fract are invisible
+    let rock = textureSample(rock_texture, rock_sampler, fract(position / ROCK_TILE)).rgb;
+    let albedo = seabed_color(position, rock);
+
+    var light = lights.ambient_color.rgb;
+    for (var i: u32 = 0u; i < lights.n_directional_lights; i = i + 1u) {
+        let directional = lights.directional_lights[i];
+        light = light + directional.color.rgb * max(dot(N, directional.direction_to_light), 0.0) * (1.0 / 3.14159265);
+    }
+    // the water above takes its share on the way down
+    let depth = max(mesh.model[3].y - in.world_position.y, 0.0);
+    let filtered = exp(-EXTINCTION * depth);
+    // faint from above through the waves, plain to see once under
+    let focused = caustics(position, time * 0.5) * (0.15 + 0.6 * amount) * exp(-depth / CAUSTICS_DEPTH);
+    return vec4<f32>(albedo * light * filtered * (1.0 + focused * step(0.001, depth)), 1.0);
+}
@@ -0,0 +1,55 @@
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+#import bevy_pbr::mesh_functions
+
+// God rays, see src/water/underwater.rs
+
+@group(1) @binding(0)
+var<uniform> color: vec4<f32>;
+// wave time wrapped to one flicker, 2π / 1.3 s
+@group(1) @binding(1)
+var<uniform> time: f32;
+// 0 above water up to 1 below
+@group(1) @binding(2)
+var<uniform> amount: f32;
+
+// m until god rays have faded out
+let RAY_DISTANCE: f32 = 40.0;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+struct FragmentInput {
+    @location(0) world_position: vec4<f32>,
+    @location(1) uv: vec2<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.clip_position = mesh_position_world_to_clip(out.world_position);
+    out.uv = vertex.uv;
+    return out;
+}
+
+@fragment
+fn god_ray(in: FragmentInput) -> @location(0) vec4<f32> {
+    // brightest in the middle and up near the surface, where the quad starts
+    let across = 1.0 - abs(in.uv.x * 2.0 - 1.0);
+    let along = smoothstep(0.0, 0.1, in.uv.y) * (1.0 - in.uv.y);
+    let flicker = 0.6 + 0.4 * sin(time * 1.3 + in.world_position.x * 0.7 + in.world_position.z * 0.3);
+    let distance = length(in.world_position.xyz - view.world_position);
+    let fade = 1.0 - smoothstep(RAY_DISTANCE * 0.5, RAY_DISTANCE, distance);
+    let alpha = across * across * along * flicker * fade * amount * 0.25;
+    return vec4<f32>(mix(color.rgb, vec3<f32>(0.9, 1.0, 0.95), 0.7), alpha);
+}
@@ -1,301 +1,646 @@
-#import bevy_pbr::mesh_view_bind_group
-#import bevy_pbr::mesh_struct
-
-struct WaterMaterial {
-    color: vec4<f32>;
-    time: f32;
-    camera: vec3<f32>;
-    wave1: vec4<f32>;
-    wave2: vec4<f32>;
-    wave3: vec4<f32>;
+#import bevy_pbr::mesh_view_bindings
+#import bevy_pbr::mesh_bindings
+
+#import bevy_pbr::utils
+#import bevy_pbr::clustered_forward
+#import bevy_pbr::lighting
+#import bevy_pbr::shadows
+
+// Must match MAX_WAVES in src/water/mod.rs
+let MAX_WAVES: u32 = 64u;
+// Must match src/water/foam.rs
+let BREAKING_JACOBIAN: f32 = 0.5;
+let WHITECAP_RANGE: f32 = 0.4;
+let FOAM_DECAY: f32 = 1.5;
+let FOAM_BUILDUP: f32 = 1.0;
+let FOAM_HISTORY_STEP: f32 = 0.5;
+// Smooth water, only the smallest ripples spread the sun's glint out
+let WATER_ROUGHNESS: f32 = 0.08;
+// Light that got into a crest and comes out the other side, tinted by the water
+let SUBSURFACE_COLOR: vec3<f32> = vec3<f32>(0.1, 0.5, 0.45);
+let FOAM_COLOR: vec3<f32> = vec3<f32>(0.92, 0.95, 0.95);
+// How far in screen space the waves shift the planar reflection
+let REFLECTION_DISTORTION: f32 = 0.05;
+
+struct Wave {
+    // xy: direction, z: wavelength, w: steepness
+    properties: vec4<f32>,
+    // at the origin, already moved on to the current wave time
+    phase: f32,
 };
-struct Transform {
-  Model : mat4x4<f32>;
+
+struct WaterWaves {
+    count: u32,
+    waves: array<Wave, 64>,
+};
+
+@group(1) @binding(0)
+var<uniform> time: f32;
+@group(1) @binding(1)
+var<uniform> color: vec4<f32>;
+@group(1) @binding(2)
+var<uniform> camera: vec3<f32>;
+@group(1) @binding(3)
+var<uniform> waves: WaterWaves;
+
+struct Fft {
+    enabled: u32,
+    size: u32,
+    length: f32,
+};
+
+@group(1) @binding(4)
+var<uniform> fft: Fft;
+// xyz: displacement
+@group(1) @binding(5)
+var fft_displacement: texture_2d<f32>;
+// xy: height slope along x and z, z: foam
+@group(1) @binding(6)
+var fft_slope: texture_2d<f32>;
+
+// Must match MAX_WAKE_SOURCES in src/water/wake.rs
+let MAX_WAKE_SOURCES: u32 = 32u;
+
+struct WakeRing {
+    // xy: center, z: radius, w: amplitude
+    ring: vec4<f32>,
+    // x: wave number, y: packet, z: churn, w: width
+    shape: vec4<f32>,
 };
-struct CameraViewProj {
-  ViewProj : mat4x4<f32>;
+
+struct Wake {
+    count: u32,
+    rings: array<WakeRing, 32>,
+};
+
+@group(1) @binding(7)
+var<uniform> wake: Wake;
+
+struct Ripple {
+    corner: vec2<f32>,
+    cell: f32,
+    size: u32,
 };
 
+@group(1) @binding(8)
+var<uniform> ripple: Ripple;
+// x: height, yz: slope along x and z
+@group(1) @binding(9)
+var ripple_height: texture_2d<f32>;
+// quaternion from the world into the sky dome's frame, see src/sky/map.rs
+@group(1) @binding(10)
+var<uniform> sky_rotation: vec4<f32>;
+@group(1) @binding(11)
+var sky_map: texture_2d<f32>;
+@group(1) @binding(12)
+var sky_map_sampler: sampler;
+// the scene above the water, mirrored and upside down
+@group(1) @binding(13)
+var reflection: texture_2d<f32>;
+@group(1) @binding(14)
+var reflection_sampler: sampler;
+
+// Must match src/water/shore.rs
+let MAX_SHOALS: u32 = 4u;
+let BREAKER_INDEX: f32 = 0.78;
+let MAX_STEEPENING: f32 = 3.0;
+let DEEP: f32 = 4.0;
+let LEE_LENGTH: f32 = 6.0;
+
+struct Shoal {
+    // xy: center, z: radius, w: slope
+    shape: vec4<f32>,
+    depth: f32,
+};
+
+struct Shore {
+    count: u32,
+    // m the water stands above the mean level
+    tide: f32,
+    shoals: array<Shoal, 4>,
+};
+
+@group(1) @binding(15)
+var<uniform> shore: Shore;
+@group(1) @binding(16)
+var rock_texture: texture_2d<f32>;
+@group(1) @binding(17)
+var rock_sampler: sampler;
+
+// The packet the waves from `first` on are held to
+struct Rogue {
+    first: u32,
+    // xy: center, zw: direction
+    shape: vec4<f32>,
+    // x: length, y: width, z: strength
+    size: vec4<f32>,
+};
+
+@group(1) @binding(18)
+var<uniform> rogue: Rogue;
+
+// Same as in seabed.wgsl, keep both in sync.
+let OCEAN_DEPTH: f32 = 120.0;
+let SHELF_DROP: f32 = 400.0;
+let BEACH_HEIGHT: f32 = 0.5;
+let REEF_CELL: f32 = 12.0;
+let REEF_DEPTH: f32 = 12.0;
+let REEF_HEIGHT: f32 = 0.6;
+let ROCK_TILE: f32 = 4.0;
+let SAND_COLOR: vec3<f32> = vec3<f32>(0.76, 0.7, 0.5);
+// light lost per m of water, red goes first
+let EXTINCTION: vec3<f32> = vec3<f32>(0.45, 0.09, 0.07);
+
+// m of water under which the open sea gets dark, and how dark
+let DARKENING_DEPTH: f32 = 20.0;
+let DEEP_WATER: f32 = 0.4;
+
 struct Vertex {
-    [[location(0)]] position: vec3<f32>;
-    [[location(1)]] normal: vec3<f32>;
-    [[location(2)]] uv: vec2<f32>;
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // x: cell size of the clipmap level, y: half side of the next level's hole
+    @location(2) level: vec2<f32>,
 };
+
 struct VertexOutput {
-    [[builtin(position)]] clip_position: vec4<f32>;
-    [[location(0)]] world_position: vec4<f32>;
-    [[location(1)]] world_normal: vec3<f32>;
-    [[location(2)]] uv: vec2<f32>;
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    // normal of the waves that are summed per vertex, the rest is added per pixel
+    @location(1) world_normal: vec3<f32>,
+    // where the point was before the waves moved it
+    @location(2) original: vec2<f32>,
+    @location(3) foam: f32,
 };
-[[group(1), binding(0)]]
-var<uniform> material: WaterMaterial;
-
-[[group(2), binding(0)]]
-var<uniform> mesh: Mesh;
-
-var<private> o_Vertex_Position : vec3<f32>;
-
-var<private> Vertex_Position : vec3<f32>;
-
-[[group(1), binding(0)]] var<uniform> x_32 : Transform;
-
-//[[group(2), binding(3)]] var<uniform> x_55 : WaterUniform_wave1;
-
-//[[group(2), binding(4)]] var<uniform> x_75 : WaterUniform_wave2;
-
-//[[group(2), binding(5)]] var<uniform> x_94 : WaterUniform_wave3;
-
-var<private> World_Position : vec4<f32>;
-
-[[group(0), binding(0)]] var<uniform> x_126 : CameraViewProj;
-
-var<private> Vertex_Normal : vec3<f32>;
-
-//[[group(2), binding(2)]] var<uniform> x_145 : WaterUniform_camera;
-
-var<private> specular : vec3<f32>;
-
-//[[group(2), binding(1)]] var<uniform> x_206 : WaterUniform_time;
-
-var<private> Vertex_Uv : vec2<f32>;
-
-var<private> v_Uv : vec2<f32>;
-
-//[[group(2), binding(0)]] var<uniform> x_311 : WaterUniform_color;
-
-var<private> gl_Position : vec4<f32>;
-
-fn gerstner_wave_vf3_vf3_vf3_vf3_vf4_(position : ptr<function, vec3<f32>>, target : ptr<function, vec3<f32>>, tangent : ptr<function, vec3<f32>>, binormal : ptr<function, vec3<f32>>, props : ptr<function, vec4<f32>>) {
-  var d : vec2<f32>;
-  var wavelength : f32;
-  var steepness : f32;
-  var k : f32;
-  var c : f32;
-  var f : f32;
-  var a : f32;
-  let x_171 : vec4<f32> = *(props);
-  d = normalize(vec2<f32>(x_171.x, x_171.y));
-  let x_177 : f32 = (*(props)).z;
-  wavelength = x_177;
-  let x_181 : f32 = (*(props)).w;
-  steepness = x_181;
-  let x_182 : f32 = steepness;
-  if ((x_182 == 0.0)) {
-    return;
-  }
-  let x_190 : f32 = wavelength;
-  k = (6.283185482 / x_190);
-  let x_194 : f32 = k;
-  c = sqrt((9.800000191 / x_194));
-  let x_198 : f32 = k;
-  let x_199 : vec2<f32> = d;
-  let x_200 : vec3<f32> = *(position);
-  let x_203 : f32 = c;
-  let x_209 : f32 = material.time;
-  f = (x_198 * (dot(x_199, vec2<f32>(x_200.x, x_200.z)) - (x_203 * x_209)));
-  let x_214 : f32 = steepness;
-  let x_215 : f32 = k;
-  a = (x_214 / x_215);
-  let x_217 : vec3<f32> = *(target);
-  let x_220 : f32 = d.x;
-  let x_221 : f32 = a;
-  let x_222 : f32 = f;
-  let x_226 : f32 = a;
-  let x_227 : f32 = f;
-  let x_230 : f32 = a;
-  let x_233 : f32 = d.y;
-  let x_234 : f32 = a;
-  let x_235 : f32 = f;
-  *(target) = (x_217 + vec3<f32>((x_220 * (x_221 * cos(x_222))), ((x_226 * sin(x_227)) + x_230), (x_233 * (x_234 * cos(x_235)))));
-  let x_241 : vec3<f32> = *(tangent);
-  let x_243 : f32 = d.x;
-  let x_246 : f32 = d.x;
-  let x_248 : f32 = steepness;
-  let x_249 : f32 = f;
-  let x_254 : f32 = d.x;
-  let x_255 : f32 = steepness;
-  let x_256 : f32 = f;
-  let x_261 : f32 = d.x;
-  let x_264 : f32 = d.y;
-  let x_266 : f32 = steepness;
-  let x_267 : f32 = f;
-  *(tangent) = (x_241 + vec3<f32>(((-(x_243) * x_246) * (x_248 * sin(x_249))), (x_254 * (x_255 * cos(x_256))), ((-(x_261) * x_264) * (x_266 * sin(x_267)))));
-  let x_273 : vec3<f32> = *(binormal);
-  let x_275 : f32 = d.x;
-  let x_278 : f32 = d.y;
-  let x_280 : f32 = steepness;
-  let x_281 : f32 = f;
-  let x_286 : f32 = d.y;
-  let x_287 : f32 = steepness;
-  let x_288 : f32 = f;
-  let x_293 : f32 = d.y;
-  let x_296 : f32 = d.y;
-  let x_298 : f32 = steepness;
-  let x_299 : f32 = f;
-  *(binormal) = (x_273 + vec3<f32>(((-(x_275) * x_278) * (x_280 * sin(x_281))), (x_286 * (x_287 * cos(x_288))), ((-(x_293) * x_296) * (x_298 * sin(x_299)))));
-  return;
+
+struct FragmentInput {
+    @builtin(front_facing) is_front: bool,
+    @builtin(position) frag_coord: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) original: vec2<f32>,
+    @location(3) foam: f32,
+};
+
+// Same as GerstnerConstants::evaluate in src/water/batch.rs, keep both in sync.
+struct ShoaledWave {
+    direction: vec2<f32>,
+    k: f32,
+    amplitude: f32,
+    phase: f32,
+    breaking: f32,
+};
+
+fn phase_gain(u: f32) -> f32 {
+    let v = clamp(u, 0.0001, DEEP);
+    let t = sqrt(tanh(v));
+    return 0.5 * log((1.0 + t) / (1.0 - t)) + atan(t) - v;
+}
+
+fn shallow_k(k: f32, depth: f32) -> f32 {
+    return k / sqrt(tanh(clamp(k * depth, 0.0001, DEEP)));
+}
+
+// x: phase gained coming in to `from_center`, y: how much the wave number has grown there
+fn shoal_gain(shoal: Shoal, k: f32, from_center: f32) -> vec2<f32> {
+    let radius = shoal.shape.z;
+    let slope = shoal.shape.w;
+    if (from_center >= radius + shoal.depth / slope) {
+        return vec2<f32>(0.0);
+    }
+    let depth = clamp((from_center - radius) * slope, 0.0, shoal.depth);
+    let phase = (phase_gain(k * shoal.depth) - phase_gain(k * depth)) / slope;
+    return vec2<f32>(phase, select(0.0, shallow_k(k, depth) - k, from_center > radius));
+}
+
+// Same as in seabed.wgsl, keep both in sync.
+fn nearest_shoal(position: vec2<f32>) -> Shoal {
+    var nearest = shore.shoals[0];
+    for (var i: u32 = 1u; i < min(shore.count, MAX_SHOALS); i = i + 1u) {
+        let shoal = shore.shoals[i];
+        let reach = shoal.shape.z + shoal.depth / shoal.shape.w;
+        let nearest_reach = nearest.shape.z + nearest.depth / nearest.shape.w;
+        if (distance(position, shoal.shape.xy) - reach < distance(position, nearest.shape.xy) - nearest_reach) {
+            nearest = shoal;
+        }
+    }
+    return nearest;
+}
+
+fn reef_hash(cell: vec2<f32>) -> f32 {
+    var q = fract(cell * vec2<f32>(0.1031, 0.1030));
+    q = q + dot(q, q.yx + 33.33);
+    return fract((q.x + q.y) * q.x);
+}
+
+// Smooth noise, one blob every `REEF_CELL` at most.
+fn reef_noise(position: vec2<f32>) -> f32 {
+    let p = position / REEF_CELL;
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    return mix(
+        mix(reef_hash(i), reef_hash(i + vec2<f32>(1.0, 0.0)), u.x),
+        mix(reef_hash(i + vec2<f32>(0.0, 1.0)), reef_hash(i + vec2<f32>(1.0, 1.0)), u.x),
+        u.y,
+    );
+}
+
+// How much of the floor at `position` a reef head covers, at `depth` without it.
+fn reef(position: vec2<f32>, depth: f32) -> f32 {
+    let shallows = step(0.0, depth) * (1.0 - smoothstep(REEF_DEPTH * 0.5, REEF_DEPTH, depth));
+    return smoothstep(0.55, 0.8, reef_noise(position)) * shallows;
 }
 
-fn main_1() {
-  var light_direction : vec3<f32>;
-  var Original_World_Position : vec4<f32>;
-  var target_1 : vec3<f32>;
-  var tangent_1 : vec3<f32>;
-  var binormal_1 : vec3<f32>;
-  var param : vec3<f32>;
-  var param_1 : vec3<f32>;
-  var param_2 : vec3<f32>;
-  var param_3 : vec3<f32>;
-  var param_4 : vec4<f32>;
-  var param_5 : vec3<f32>;
-  var param_6 : vec3<f32>;
-  var param_7 : vec3<f32>;
-  var param_8 : vec3<f32>;
-  var param_9 : vec4<f32>;
-  var param_10 : vec3<f32>;
-  var param_11 : vec3<f32>;
-  var param_12 : vec3<f32>;
-  var param_13 : vec3<f32>;
-  var param_14 : vec4<f32>;
-  var light_reflect_direction : vec3<f32>;
-  var view_direction : vec3<f32>;
-  var light_see_direction : f32;
-  var shininess : f32;
-  light_direction = vec3<f32>(0.577350259, 0.577350259, -0.577350259);
-  let x_27 : vec3<f32> = Vertex_Position;
-  o_Vertex_Position = x_27;
-  let x_37 : mat4x4<f32> = x_32.Model;
-  let x_38 : vec3<f32> = Vertex_Position;
-  Original_World_Position = (x_37 * vec4<f32>(x_38.x, x_38.y, x_38.z, 1.0));
-  let x_46 : vec4<f32> = Original_World_Position;
-  target_1 = vec3<f32>(x_46.x, x_46.y, x_46.z);
-  tangent_1 = vec3<f32>(1.0, 0.0, 0.0);
-  binormal_1 = vec3<f32>(0.0, 0.0, 1.0);
-  let x_57 : vec4<f32> = Original_World_Position;
-  param = vec3<f32>(x_57.x, x_57.y, x_57.z);
-  let x_60 : vec3<f32> = target_1;
-  param_1 = x_60;
-  let x_62 : vec3<f32> = tangent_1;
-  param_2 = x_62;
-  let x_64 : vec3<f32> = binormal_1;
-  param_3 = x_64;
-  let x_68 : vec4<f32> = material.wave1;
-  param_4 = x_68;
-  gerstner_wave_vf3_vf3_vf3_vf3_vf4_(&(param), &(param_1), &(param_2), &(param_3), &(param_4));
-  let x_70 : vec3<f32> = param_1;
-  target_1 = x_70;
-  let x_71 : vec3<f32> = param_2;
-  tangent_1 = x_71;
-  let x_72 : vec3<f32> = param_3;
-  binormal_1 = x_72;
-  let x_77 : vec4<f32> = Original_World_Position;
-  param_5 = vec3<f32>(x_77.x, x_77.y, x_77.z);
-  let x_80 : vec3<f32> = target_1;
-  param_6 = x_80;
-  let x_82 : vec3<f32> = tangent_1;
-  param_7 = x_82;
-  let x_84 : vec3<f32> = binormal_1;
-  param_8 = x_84;
-  let x_87 : vec4<f32> = material.wave2;
-  param_9 = x_87;
-  gerstner_wave_vf3_vf3_vf3_vf3_vf4_(&(param_5), &(param_6), &(param_7), &(param_8), &(param_9));
-  let x_89 : vec3<f32> = param_6;
-  target_1 = x_89;
-  let x_90 : vec3<f32> = param_7;
-  tangent_1 = x_90;
-  let x_91 : vec3<f32> = param_8;
-  binormal_1 = x_91;
-  let x_96 : vec4<f32> = Original_World_Position;
-  param_10 = vec3<f32>(x_96.x, x_96.y, x_96.z);
-  let x_99 : vec3<f32> = target_1;
-  param_11 = x_99;
-  let x_101 : vec3<f32> = tangent_1;
-  param_12 = x_101;
-  let x_103 : vec3<f32> = binormal_1;
-  param_13 = x_103;
-  let x_106 : vec4<f32> = material.wave3;
-  param_14 = x_106;
-  gerstner_wave_vf3_vf3_vf3_vf3_vf4_(&(param_10), &(param_11), &(param_12), &(param_13), &(param_14));
-  let x_108 : vec3<f32> = param_11;
-  target_1 = x_108;
-  let x_109 : vec3<f32> = param_12;
-  tangent_1 = x_109;
-  let x_110 : vec3<f32> = param_13;
-  binormal_1 = x_110;
-  let x_113 : vec3<f32> = target_1;
-  World_Position = vec4<f32>(x_113.x, x_113.y, x_113.z, 1.0);
-  let x_128 : mat4x4<f32> = x_126.ViewProj;
-  let x_129 : vec4<f32> = World_Position;
-  gl_Position = (x_128 * x_129);
-  let x_133 : vec3<f32> = binormal_1;
-  let x_134 : vec3<f32> = tangent_1;
-  Vertex_Normal = normalize(cross(x_133, x_134));
-  let x_138 : vec3<f32> = light_direction;
-  let x_139 : vec3<f32> = -(x_138);
-  let x_140 : vec3<f32> = Vertex_Normal;
-  light_reflect_direction = reflect(x_139, x_140);
-  let x_148 : vec3<f32> = material.camera;
-  let x_149 : vec4<f32> = World_Position;
-  view_direction = normalize((x_148 - vec3<f32>(x_149.x, x_149.y, x_149.z)));
-  let x_155 : vec3<f32> = light_reflect_direction;
-  let x_156 : vec3<f32> = view_direction;
-  light_see_direction = max(0.0, dot(x_155, x_156));
-  let x_160 : f32 = light_see_direction;
-  shininess = pow(x_160, 100.0);
-  let x_166 : f32 = shininess;
-  specular = (vec3<f32>(0.5, 0.5, 0.5) * x_166);
-  return;
+// The floor below the mean level without reefs: the slope the waves shoal on around the nearest island, up
+// to a low beach, and past its reach a shelf dropping off into the deep ocean.
+fn shelf_depth(position: vec2<f32>) -> f32 {
+    if (min(shore.count, MAX_SHOALS) == 0u) {
+        return OCEAN_DEPTH;
+    }
+    let shoal = nearest_shoal(position);
+    let radius = shoal.shape.z;
+    let reach = radius + shoal.depth / shoal.shape.w;
+    let from_center = distance(position, shoal.shape.xy);
+    let slope = clamp((from_center - radius) * shoal.shape.w, -BEACH_HEIGHT, shoal.depth);
+    return mix(slope, OCEAN_DEPTH, smoothstep(reach, reach + SHELF_DROP, from_center));
 }
 
-struct main_out {
-  [[location(3)]]
-  o_Vertex_Position_1 : vec3<f32>;
-  [[location(2)]]
-  World_Position_1 : vec4<f32>;
-  [[builtin(position)]]
-  gl_Position : vec4<f32>;
-  [[location(1)]]
-  Vertex_Normal_1 : vec3<f32>;
-  [[location(4)]]
-  specular_1 : vec3<f32>;
-  [[location(0)]]
-  v_Uv_1 : vec2<f32>;
+// m of water over the floor at `position` at the current tide, negative on land.
+fn seabed_depth(position: vec2<f32>) -> f32 {
+    let depth = shelf_depth(position);
+    return depth * (1.0 - REEF_HEIGHT * reef(position, depth)) + shore.tide;
+}
+
+// Sand with ripples, and the rocks of the reefs. `rock` is the rock texture at `position`.
+fn seabed_color(position: vec2<f32>, rock: vec3<f32>) -> vec3<f32> {
+    let ripples = 0.9 + 0.1 * sin(dot(position, vec2<f32>(1.7, 0.6)) * 3.0);
+    return mix(SAND_COLOR * ripples, rock, reef(position, shelf_depth(position)));
+}
+
+// Same as ShorePoint in src/water/shore.rs, keep both in sync.
+// Where a point lies against the nearest shoal, the same for every wave there.
+struct ShorePoint {
+    // none without shoals
+    found: bool,
+    shoal: Shoal,
+    offset: vec2<f32>,
+    from_center: f32,
+    depth: f32,
 };
 
-[[stage(vertex)]]
-fn main([[location(0)]] Vertex_Position_param : vec3<f32>, [[location(1)]] Vertex_Uv_param : vec2<f32>) -> main_out {
-  Vertex_Position = Vertex_Position_param;
-  Vertex_Uv = Vertex_Uv_param;
-  main_1();
-  return main_out(o_Vertex_Position, World_Position, gl_Position, Vertex_Normal, specular, v_Uv);
+// Same as Shore::at in src/water/shore.rs, keep both in sync.
+fn shore_point(position: vec2<f32>) -> ShorePoint {
+    var out: ShorePoint;
+    out.found = min(shore.count, MAX_SHOALS) != 0u;
+    if (!out.found) {
+        return out;
+    }
+    // the same floor, further under water at high tide
+    var nearest = nearest_shoal(position);
+    nearest.shape.z = nearest.shape.z - shore.tide / nearest.shape.w;
+    nearest.depth = nearest.depth + shore.tide;
+    out.shoal = nearest;
+    out.offset = position - nearest.shape.xy;
+    out.from_center = max(length(out.offset), 0.001);
+    out.depth = clamp((out.from_center - nearest.shape.z) * nearest.shape.w, 0.0, nearest.depth);
+    return out;
 }
 
+// Same as ShorePoint::shoal in src/water/shore.rs, keep both in sync.
+fn shoal_wave(shore_at: ShorePoint, direction: vec2<f32>, k0: f32, amplitude: f32) -> ShoaledWave {
+    var out = ShoaledWave(direction, k0, amplitude, 0.0, 0.0);
+    if (!shore_at.found) {
+        return out;
+    }
+    let nearest = shore_at.shoal;
+    let radius = nearest.shape.z;
+    let slope = nearest.shape.w;
+    let reach = radius + nearest.depth / slope;
+    let offset = shore_at.offset;
+    let from_center = shore_at.from_center;
+    let along = dot(direction, offset);
+    let across = direction.x * offset.y - direction.y * offset.x;
+    if (from_center >= reach && along <= 0.0) {
+        return out;
+    }
+
+    // the calm behind the island
+    var lee = 1.0;
+    if (along > 0.0) {
+        let behind = 1.0 - smoothstep(radius, 2.0 * radius, abs(across));
+        let filled = exp(-along / (LEE_LENGTH * radius));
+        lee = 1.0 - behind * filled * smoothstep(0.0, radius, along);
+    }
+
+    // gained on the straight way in, and lost again climbing out past the closest point
+    let gain = shoal_gain(nearest, k0, from_center);
+    let outwards = offset / from_center;
+    var wave_vector = k0 * direction - gain.y * outwards;
+    out.phase = gain.x;
+    if (along > 0.0) {
+        let closest = shoal_gain(nearest, k0, abs(across));
+        let sideways = vec2<f32>(-direction.y, direction.x) * sign(across);
+        out.phase = 2.0 * closest.x - gain.x;
+        wave_vector = k0 * direction + gain.y * outwards - 2.0 * closest.y * sideways;
+    }
+    let k = length(wave_vector);
+    let steepest = MAX_STEEPENING * k0 * amplitude / k;
+    out.direction = wave_vector / k;
+    out.k = k;
+    if (from_center >= reach) {
+        out.amplitude = min(amplitude * lee, steepest);
+        return out;
+    }
+
+    // slower waves bunch up: the same energy flux at a lower group speed
+    let depth = shore_at.depth;
+    let ku = clamp(shallow_k(k0, depth) * depth, 0.0001, 10.0);
+    let group = sqrt(tanh(clamp(k0 * depth, 0.0001, DEEP))) * (1.0 + 2.0 * ku / sinh(2.0 * ku));
+    let shoaled = amplitude * lee / sqrt(group);
+    let limit = min(BREAKER_INDEX / 2.0 * depth, steepest);
+    out.amplitude = min(shoaled, limit);
+    if (shoaled > limit) {
+        out.breaking = min(shoaled / max(limit, 0.000001) - 1.0, 1.0);
+    }
+    return out;
+}
 
-//[[stage(vertex)]]
-//fn vertex(vertex: Vertex) -> VertexOutput {
-//    let world_position = mesh.model * vec4<f32>(vertex.position, 1.0);
-//
-//    var out: VertexOutput;
-//    out.uv = vertex.uv;
-//    out.world_position = world_position;
-//    out.clip_position = view.view_proj * world_position;
-//    out.world_normal = mat3x3<f32>(
-//        mesh.inverse_transpose_model[0].xyz,
-//        mesh.inverse_transpose_model[1].xyz,
-//        mesh.inverse_transpose_model[2].xyz
-//    ) * vertex.normal;
-//#ifdef VERTEX_TANGENTS
-//    out.world_tangent = vec4<f32>(
-//        mat3x3<f32>(
-//            mesh.model[0].xyz,
-//            mesh.model[1].xyz,
-//            mesh.model[2].xyz
-//        ) * vertex.tangent.xyz,
-//        vertex.tangent.w
-//    );
-//#endif
-//    return out;
-//}
+// Same as Envelope::at in src/water/rogue.rs, keep both in sync.
+// How much of wave `i` there is at `position`, all of it unless it is a rogue wave's.
+fn rogue_envelope(i: u32, position: vec2<f32>) -> f32 {
+    if (i < rogue.first) {
+        return 1.0;
+    }
+    let offset = position - rogue.shape.xy;
+    let d = rogue.shape.zw;
+    let along = dot(d, offset) / rogue.size.x;
+    let across = (d.x * offset.y - d.y * offset.x) / rogue.size.y;
+    return rogue.size.z * exp(-along * along - across * across);
+}
+
+fn gerstner_wave(
+    position: vec3<f32>,
+    shore_at: ShorePoint,
+    wave: Wave,
+    envelope: f32,
+    displaced: ptr<function, vec3<f32>>,
+    tangent: ptr<function, vec3<f32>>,
+    binormal: ptr<function, vec3<f32>>,
+) {
+    let props = wave.properties;
+    let steepness = props.w;
+    if (steepness == 0.0) {
+        return;
+    }
+    let k0 = 6.283185307 / props.z;
+    let shoaled = shoal_wave(shore_at, normalize(props.xy), k0, steepness / k0 * envelope);
+    let d = shoaled.direction;
+    let f = k0 * dot(normalize(props.xy), position.xz) + wave.phase + shoaled.phase;
+    let a = shoaled.amplitude;
+    let q = shoaled.k * a;
+
+    *displaced = *displaced + vec3<f32>(
+        d.x * (a * cos(f)),
+        a * sin(f) + a,
+        d.y * (a * cos(f)),
+    );
+    *tangent = *tangent + vec3<f32>(
+        -d.x * d.x * (q * sin(f)),
+        d.x * (q * cos(f)),
+        -d.x * d.y * (q * sin(f)),
+    );
+    *binormal = *binormal + vec3<f32>(
+        -d.x * d.y * (q * sin(f)),
+        d.y * (q * cos(f)),
+        -d.y * d.y * (q * sin(f)),
+    );
+}
+
+// Same as GerstnerConstants::foam in src/water/batch.rs, keep both in sync.
+// The four lanes are now and the three history steps before.
+fn gerstner_foam(position: vec3<f32>) -> f32 {
+    var dx_dx = vec4<f32>(0.0);
+    var dz_dz = vec4<f32>(0.0);
+    var dx_dz = vec4<f32>(0.0);
+    var surf = 0.0;
+    var amplitude = 0.0;
+    let history = vec4<f32>(0.0, 1.0, 2.0, 3.0) * FOAM_HISTORY_STEP;
+    let shore_at = shore_point(position.xz);
+    let count = min(waves.count, MAX_WAVES);
+    for (var i: u32 = 0u; i < count; i = i + 1u) {
+        let props = waves.waves[i].properties;
+        let steepness = props.w;
+        if (steepness == 0.0) {
+            continue;
+        }
+        let k0 = 6.283185307 / props.z;
+        let omega = k0 * sqrt(9.8 / k0);
+        let envelope = rogue_envelope(i, position.xz);
+        let shoaled = shoal_wave(shore_at, normalize(props.xy), k0, steepness / k0 * envelope);
+        let d = shoaled.direction;
+        let f = k0 * dot(normalize(props.xy), position.xz) + waves.waves[i].phase + shoaled.phase;
+        surf = surf + shoaled.breaking * shoaled.amplitude * (0.5 + 0.5 * sin(f));
+        amplitude = amplitude + shoaled.amplitude;
+        let s = shoaled.k * shoaled.amplitude * sin(f + omega * history);
+        dx_dx = dx_dx - d.x * d.x * s;
+        dz_dz = dz_dz - d.y * d.y * s;
+        dx_dz = dx_dz - d.x * d.y * s;
+    }
+    let jacobian = (1.0 + dx_dx) * (1.0 + dz_dz) - dx_dz * dx_dz;
+    let whitecap = clamp((BREAKING_JACOBIAN - jacobian) / WHITECAP_RANGE, vec4<f32>(0.0), vec4<f32>(1.0));
+    let weight = FOAM_BUILDUP * FOAM_HISTORY_STEP * exp(-history / FOAM_DECAY);
+    return max(min(dot(whitecap, weight), 1.0), surf / max(amplitude, 0.000001));
+}
+
+// Same as WakeRing::at in src/water/wake.rs, keep both in sync.
+// Returns the height in x, the slope in yz and the churn in w.
+fn wake_ring(position: vec2<f32>, ring: WakeRing) -> vec4<f32> {
+    let offset = position - ring.ring.xy;
+    let distance = length(offset);
+    let x = distance - ring.ring.z;
+    let width = ring.shape.w;
+    let churn = ring.shape.z * exp(-(distance / width) * (distance / width));
+    let k = ring.shape.x;
+    let packet = ring.shape.y;
+    if (abs(x) > 3.0 * packet || distance == 0.0) {
+        return vec4<f32>(0.0, 0.0, 0.0, churn);
+    }
+    let envelope = ring.ring.w * exp(-(x / packet) * (x / packet));
+    let d_height = envelope * (-2.0 * x / (packet * packet) * cos(k * x) - k * sin(k * x));
+    let slope = offset / distance * d_height;
+    return vec4<f32>(envelope * cos(k * x), slope, churn);
+}
+
+// Bilinear and nothing off the grid, same as Ripples::at in src/water/ripples.rs
+fn ripple_sample(position: vec2<f32>) -> vec4<f32> {
+    let texel = (position - ripple.corner) / ripple.cell;
+    let base = floor(texel);
+    let last = f32(ripple.size) - 1.0;
+    if (base.x < 0.0 || base.y < 0.0 || base.x >= last || base.y >= last) {
+        return vec4<f32>(0.0);
+    }
+    let t = texel - base;
+    let i = vec2<i32>(base);
+    return mix(
+        mix(textureLoad(ripple_height, i, 0), textureLoad(ripple_height, i + vec2<i32>(1, 0), 0), t.x),
+        mix(textureLoad(ripple_height, i + vec2<i32>(0, 1), 0), textureLoad(ripple_height, i + vec2<i32>(1, 1), 0), t.x),
+        t.y,
+    );
+}
+
+// Bilinear and wrapping, same as FftOcean::wave_data_at_point
+fn fft_texel(texture: texture_2d<f32>, texel: vec2<i32>) -> vec4<f32> {
+    let size = i32(fft.size);
+    let wrapped = ((texel % size) + size) % size;
+    return textureLoad(texture, wrapped, 0);
+}
+
+fn fft_sample(texture: texture_2d<f32>, position: vec2<f32>) -> vec4<f32> {
+    let texel = position / fft.length * f32(fft.size);
+    let base = floor(texel);
+    let t = texel - base;
+    let i = vec2<i32>(base);
+    return mix(
+        mix(fft_texel(texture, i), fft_texel(texture, i + vec2<i32>(1, 0)), t.x),
+        mix(fft_texel(texture, i + vec2<i32>(0, 1)), fft_texel(texture, i + vec2<i32>(1, 1)), t.x),
+        t.y,
+    );
+}
+
+// Places a vertex of the clipmap from src/water/mesh.rs around the water transform.
+// Each level snaps to twice its own cell size, so its vertices stay put on the world
+// grid. Towards the next level the cells morph into the coarser ones, and whatever
+// sticks out of that level's hole is folded onto its edge, closing the seam.
+fn clipmap_position(local: vec3<f32>, level: vec2<f32>) -> vec2<f32> {
+    let center = mesh.model[3].xz;
+    let cell = level.x;
+    let coarse = 2.0 * cell;
+    var position = floor(center / coarse) * coarse + local.xz;
+
+    let hole = level.y;
+    if (hole > 0.0) {
+        let hole_center = floor(center / (2.0 * coarse)) * 2.0 * coarse;
+        let offset = abs(position - center);
+        let distance = max(offset.x, offset.y);
+        // clear of this level's own hole, and fully coarse before the next hole's edge
+        let start = hole * 0.5 + 2.0 * coarse;
+        let end = hole - 2.0 * coarse;
+        let t = clamp((distance - start) / (end - start), 0.0, 1.0);
+        position = position - fract(position / coarse) * coarse * t;
+        position = clamp(position, hole_center - vec2<f32>(hole), hole_center + vec2<f32>(hole));
+    }
+    return position;
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let placed = clipmap_position(vertex.position, vertex.level);
+    let original_world_position = vec4<f32>(placed.x, mesh.model[3].y, placed.y, 1.0);
+    var displaced = original_world_position.xyz;
+    var tangent = vec3<f32>(1.0, 0.0, 0.0);
+    var binormal = vec3<f32>(0.0, 0.0, 1.0);
+    var foam = 0.0;
+    if (fft.enabled != 0u) {
+        displaced = displaced + fft_sample(fft_displacement, original_world_position.xz).xyz;
+        // the slope is sampled per pixel, only the foam is needed here
+        foam = fft_sample(fft_slope, original_world_position.xz).z;
+    } else {
+        let count = min(waves.count, MAX_WAVES);
+        let shore_at = shore_point(original_world_position.xz);
+        for (var i: u32 = 0u; i < count; i = i + 1u) {
+            let envelope = rogue_envelope(i, original_world_position.xz);
+            gerstner_wave(original_world_position.xyz, shore_at, waves.waves[i], envelope, &displaced, &tangent, &binormal);
+        }
+        foam = gerstner_foam(original_world_position.xyz);
+    }
+
+    let wake_count = min(wake.count, MAX_WAKE_SOURCES);
+    for (var i: u32 = 0u; i < wake_count; i = i + 1u) {
+        let ring = wake_ring(original_world_position.xz, wake.rings[i]);
+        displaced.y = displaced.y + ring.x;
+        tangent.y = tangent.y + ring.y;
+        binormal.y = binormal.y + ring.z;
+        foam = max(foam, ring.w);
+    }
+
+    displaced.y = displaced.y + ripple_sample(original_world_position.xz).x;
+
+    var out: VertexOutput;
+    out.world_position = vec4<f32>(displaced, 1.0);
+    out.clip_position = view.view_proj * out.world_position;
+    out.world_normal = normalize(cross(binormal, tangent));
+    out.original = original_world_position.xz;
+    out.foam = foam;
+    return out;
+}
+
+// The vertex normal with the detail that is too fine for the clipmap's vertices: the
+// FFT tile and the ripples.
+fn pixel_normal(in: FragmentInput) -> vec3<f32> {
+    var slope = -in.world_normal.xz / in.world_normal.y;
+    if (fft.enabled != 0u) {
+        slope = slope + fft_sample(fft_slope, in.original).xy;
+    }
+    slope = slope + ripple_sample(in.original).yz;
+    return normalize(vec3<f32>(-slope.x, 1.0, -slope.y));
+}
+
+// Sun or moon, lanterns and the ambient light on the water: the body of the water lit
+// from within, the glint of the lights on the surface, and light shining through crests.
+// Reflections of the sky are added on top by the caller. `base` is the color of the water
+// or the foam on it.
+fn lit_water(in: FragmentInput, base: vec3<f32>, N: vec3<f32>, V: vec3<f32>) -> vec3<f32> {
+    let roughness = perceptualRoughnessToRoughness(WATER_ROUGHNESS);
+    let NdotV = max(dot(N, V), 0.0001);
+    let R = reflect(-V, N);
+    let F0 = vec3<f32>(0.02);
+    let receives_shadows = (mesh.flags & MESH_FLAGS_SHADOW_RECEIVER_BIT) != 0u;
+    // crests stand higher than the mean level, that is where light gets through
+    let crest = saturate((in.world_position.y - mesh.model[3].y) * 0.5);
+
+    var light = base * lights.ambient_color.rgb;
+
+    let view_z = dot(vec4<f32>(
+        view.inverse_view[0].z,
+        view.inverse_view[1].z,
+        view.inverse_view[2].z,
+        view.inverse_view[3].z
+    ), in.world_position);
+    let cluster_index = fragment_cluster_index(in.frag_coord.xy, view_z, false);
+    let offset_and_counts = unpack_offset_and_counts(cluster_index);
+    for (var i: u32 = offset_and_counts[0]; i < offset_and_counts[0] + offset_and_counts[1]; i = i + 1u) {
+        let light_id = get_light_id(i);
+        let lamp = point_lights.data[light_id];
+        var shadow = 1.0;
+        if (receives_shadows && (lamp.flags & POINT_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u) {
+            shadow = fetch_point_shadow(light_id, in.world_position, N);
+        }
+        light = light + point_light(in.world_position.xyz, lamp, roughness, NdotV, N, V, R, F0, base) * shadow;
+    }
+    for (var i: u32 = offset_and_counts[0] + offset_and_counts[1]; i < offset_and_counts[0] + offset_and_counts[1] + offset_and_counts[2]; i = i + 1u) {
+        let light_id = get_light_id(i);
+        let spot = point_lights.data[light_id];
+        var shadow = 1.0;
+        if (receives_shadows && (spot.flags & POINT_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u) {
+            shadow = fetch_spot_shadow(light_id, in.world_position, N);
+        }
+        light = light + spot_light(in.world_position.xyz, spot, roughness, NdotV, N, V, R, F0, base) * shadow;
+    }
+
+    for (var i: u32 = 0u; i < lights.n_directional_lights; i = i + 1u) {
+        let directional = lights.directional_lights[i];
+        var shadow = 1.0;
+        if (receives_shadows && (directional.flags & DIRECTIONAL_LIGHT_FLAGS_SHADOWS_ENABLED_BIT) != 0u) {
+            shadow = fetch_directional_shadow(i, in.world_position, N);
+        }
+        let L = directional.direction_to_light;
+        light = light + directional_light(directional, roughness, NdotV, N, V, R, F0, base) * shadow;
+        // looking towards the light through a crest
+        let through = pow(saturate(dot(V, -L)), 4.0) * crest;
+        light = light + SUBSURFACE_COLOR * directional.color.rgb * through * shadow * (1.0 / PI);
+    }
+    return light;
+}
 
 fn hash22(p: vec2<f32>, iTime: f32) -> vec2<f32> {
     // Faster, but probably doesn't disperse things as nicely as other ways.
@@ -346,8 +691,73 @@ fn Voronoi3Tap(p: vec2<f32>, iTime: f32) -> f32 {
 
 }
 
-[[stage(fragment)]]
-fn fragment() -> [[location(0)]] vec4<f32> {
-    return material.color;
+// Light coming down from the sky as a whole, for what is seen from under the water.
+fn sky_light() -> vec3<f32> {
+    var light = lights.ambient_color.rgb;
+    for (var i: u32 = 0u; i < lights.n_directional_lights; i = i + 1u) {
+        light = light + lights.directional_lights[i].color.rgb * (1.0 / PI);
+    }
+    return light;
+}
+
+fn rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    let t = 2.0 * cross(q.xyz, v);
+    return v + q.w * t + cross(q.xyz, t);
+}
+
+fn sky_reflection(direction: vec3<f32>) -> vec3<f32> {
+    let d = rotate(sky_rotation, direction);
+    let uv = vec2<f32>(atan2(d.x, d.z) / 6.2831853 + 0.5, acos(clamp(d.y, -1.0, 1.0)) / 3.14159265);
+    // no mips, and the seam at the back would confuse the derivatives anyway
+    return textureSampleLevel(sky_map, sky_map_sampler, uv, 0.0).rgb;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    // thin foam breaks up into cells, thick foam covers everything
+    let cells = Voronoi3Tap(in.world_position.xz * 0.7, time);
+    let coverage = smoothstep(0.0, 1.0, in.foam * 2.0 - cells);
+    let view_direction = normalize(in.world_position.xyz - camera);
+    let normal = pixel_normal(in);
+
+    // waves tilted away from the camera would reflect the sea, they see the sky above it
+    let reflected = reflect(view_direction, normal);
+    var mirrored = sky_reflection(vec3<f32>(reflected.x, abs(reflected.y), reflected.z));
+    // close by things above the water, bent by the waves like the sky
+    let screen = in.frag_coord.xy / vec2<f32>(view.width, view.height);
+    let distorted = vec2<f32>(screen.x, 1.0 - screen.y) + normal.xz * REFLECTION_DISTORTION;
+    let planar = textureSample(reflection, reflection_sampler, distorted);
+    mirrored = mix(mirrored, planar.rgb, planar.a);
+    // Schlick's Fresnel for water, 2% straight down up to everything at grazing angles
+    let cos_view = clamp(dot(-view_direction, normal), 0.0, 1.0);
+    let fresnel = 0.02 + 0.98 * pow(1.0 - cos_view, 5.0);
+
+    // the floor seen through the water where the refracted view ray meets it, lit by
+    // what is left of the sky's light down there and dimmed again on the way back up
+    let column = max(seabed_depth(in.original) + in.world_position.y - mesh.model[3].y, 0.0);
+    let eta = 1.0 / 1.33;
+    let bent = eta * cos_view - sqrt(1.0 - eta * eta * (1.0 - cos_view * cos_view));
+    let refracted = eta * view_direction + bent * normal;
+    let path = column / max(-refracted.y, 0.1);
+    let bottom = in.original + refracted.xz * path;
+    let rock = textureSample(rock_texture, rock_sampler, fract(bottom / ROCK_TILE)).rgb;
+    let seabed = seabed_color(bottom, rock) * sky_light() * exp(-EXTINCTION * column);
+    // foam and long ways through the water hide it, red first, so shallows turn turquoise
+    let seen = exp(-EXTINCTION * path) * (1.0 - coverage);
+    // less of the light gets back up out of deep water, the open sea is dark
+    let water = color.rgb * mix(1.0, DEEP_WATER, 1.0 - exp(-column / DARKENING_DEPTH));
+
+    // foam is rough and white, it covers up the reflections
+    let base = mix(water, FOAM_COLOR, coverage);
+    let body = mix(lit_water(in, base, normal, -view_direction), seabed, seen);
+    var surface = mix(body, mirrored, fresnel * (1.0 - coverage));
+    if (!in.is_front) {
+        // from below the sky only shows through Snell's window, past 48.6° off straight up
+        // the surface mirrors the dark water instead
+        let window = smoothstep(0.62, 0.70, dot(view_direction, normal));
+        let below = mix(color.rgb * 0.3, vec3<f32>(0.75, 0.9, 0.95), window);
+        surface = mix(below, FOAM_COLOR, coverage) * sky_light();
+    }
+    return vec4<f32>(reinhard_luminance(surface), color.a);
 }
 
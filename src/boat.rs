@@ -1,22 +1,36 @@
+use crate::boat_definition::{BoatDefinition, BoatDefinitionLoader};
+use crate::sail::{self, BoatSail, Rig};
 use crate::water;
-use crate::water::Water;
+use crate::water::hull::Hull;
+use crate::water::ripples::SplashEvent;
+use crate::water::{Water, WaterSurface, Wind};
 use crate::AppState;
 use bevy::prelude::*;
 // use bevy_inspector_egui::Inspectable;
-use core::f32::consts::FRAC_PI_4;
+use core::f32::consts::{FRAC_PI_4, PI};
 
 #[derive(Component)]
 pub struct PlayerBoat {
     pub throttle: f32,
     pub steer: f32,
 
-    pub velocity: Vec3,
+    pub velocity: Vec3, // through the water
     pub speed: f32,
+    pub speed_over_ground: f32,
 
     pub world_rotation: f32, // y angle in radians
     pub last_normal: Quat,
     pub nose_angle: f32,
     pub airborne: Option<(Vec3, f32, f32)>,
+    pub hull: Hull,
+    pub definition: Handle<BoatDefinition>,
+    /// the definition the parts and buoyancy are from, None until it has loaded
+    pub fitted: Option<Handle<BoatDefinition>>,
+    /// close enough to an island to change boats
+    pub moored: bool,
+    pub rig: Rig,
+    /// seconds left upside down after rolling over, 0 while upright
+    pub capsized: f32,
 
     pub exhaust_last: f64,
 }
@@ -24,6 +38,27 @@ pub struct PlayerBoat {
 #[derive(Component)]
 pub struct BoatJet;
 
+/// The boats to choose from at an island, in the order they come up.
+pub struct Fleet {
+    pub boats: Vec<Handle<BoatDefinition>>,
+}
+
+const FLEET: [&str; 4] = [
+    "boats/raft.boat.ron",
+    "boats/correolas.boat.ron",
+    "boats/barquica.boat.ron",
+    "boats/lancha.boat.ron",
+];
+
+impl Fleet {
+    /// The boat after `current`, the first one again after the last.
+    pub fn next(&self, current: &Handle<BoatDefinition>) -> Handle<BoatDefinition> {
+        let index = self.boats.iter().position(|boat| boat == current);
+        let next = index.map_or(0, |index| (index + 1) % self.boats.len());
+        self.boats[next].clone()
+    }
+}
+
 #[derive(Component)]
 pub struct BoatExhaustParticle;
 
@@ -33,81 +68,47 @@ pub struct MoveEvent {
     pub translation: Vec3,
 }
 
-fn paddle_transform() -> Transform {
-    Transform::from_translation(Vec3::new(0.45, 0.0, 1.9))
-}
-
 pub fn add_systems(app: &mut bevy::prelude::App) -> &mut bevy::prelude::App {
-    app.add_startup_system(boat_startup_system)
+    app.add_asset::<BoatDefinition>()
+        .init_asset_loader::<BoatDefinitionLoader>()
+        .add_startup_system(boat_startup_system)
+        .add_system(boat_definition_system.before("physics"))
         // must run after input to avoid some jankiness
         .add_system_set(
             SystemSet::on_update(AppState::InGame)
-                .with_system(boat_physics_system.label("physics").after("input")),
+                .with_system(paddle_system.after("input").before("physics"))
+                .with_system(boat_physics_system.label("physics").after("input"))
+                .with_system(sail::sail_system.after("physics")),
         )
 }
 
-fn boat_startup_system(
-    mut commands: Commands,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
-) {
-    let material_handle = materials.add(StandardMaterial {
-        base_color: Color::WHITE,
-        base_color_texture: Some(asset_server.load("textures/wood.png")),
-        // roughness: 0.2,
-        metallic: 0.5,
-        reflectance: 0.8,
-        // unlit: true,
-        ..Default::default()
-    });
-
+fn boat_startup_system(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let fleet = Fleet {
+        boats: FLEET.iter().map(|path| asset_server.load(*path)).collect(),
+    };
+    // the meshes come with the definition, see boat_definition_system
     commands
-        .spawn_bundle(PbrBundle {
-            mesh: asset_server.load("raft.glb#Mesh0/Primitive0"),
-            material: material_handle,
-            ..Default::default()
-        })
+        .spawn_bundle(SpatialBundle::default())
         .insert(PlayerBoat {
             throttle: 0.,
             steer: 0.,
             velocity: Vec3::Z,
             speed: 0.,
+            speed_over_ground: 0.,
             world_rotation: 0.,
             last_normal: Quat::IDENTITY,
             nose_angle: 0.,
             airborne: None,
+            hull: Hull::default(),
+            definition: fleet.boats[0].clone(),
+            fitted: None,
+            moored: false,
+            rig: Rig::default(),
+            capsized: 0.,
             exhaust_last: 0.,
         })
-        .insert(Name::new("PlayerBoat"))
-        .with_children(|parent| {
-            parent
-                .spawn_bundle(PbrBundle {
-                    transform: paddle_transform(),
-                    ..Default::default()
-                })
-                .insert(BoatJet)
-                .insert(Name::new("BoatJet"))
-                .with_children(|parent| {
-                    parent.spawn_bundle(PbrBundle {
-                        mesh: asset_server.load("raft.glb#Mesh1/Primitive0"),
-                        material: materials.add(Color::rgb(0.1, 0.0, 0.0).into()),
-                        ..Default::default()
-                    });
-                });
-
-            let sail_material_handle = materials.add(StandardMaterial {
-                base_color: Color::WHITE,
-                double_sided: true,
-                ..Default::default()
-            });
-            parent
-                .spawn_bundle(PbrBundle {
-                    mesh: asset_server.load("raft.glb#Mesh2/Primitive0"),
-                    material: sail_material_handle,
-                    ..Default::default()
-                })
-                .insert(Name::new("Sail"));
-        });
+        .insert(Name::new("PlayerBoat"));
+    commands.insert_resource(fleet);
 
     // let texture = asset_server.load("splash.png");
     // for i in 0..0 {
@@ -132,51 +133,222 @@ fn boat_startup_system(
     // }
 }
 
-const DRAG: f32 = 0.2;
-const FRICTION: f32 = 2.;
-const ENGINE_FORCE: f32 = 2000.;
-const BOAT_MASS: f32 = 20.;
+/// Fits the boat out to its definition once it has loaded, when it is changed for another
+/// boat of the fleet, and again every time the file changes: the parts are spawned anew
+/// and the buoyancy points replaced. The handling is read from the definition as the boat
+/// goes, everything else the boat carries over.
+fn boat_definition_system(
+    mut commands: Commands,
+    mut ev_definition: EventReader<AssetEvent<BoatDefinition>>,
+    definitions: Res<Assets<BoatDefinition>>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut boat_query: Query<(Entity, &mut PlayerBoat)>,
+) {
+    let modified: Vec<Handle<BoatDefinition>> = ev_definition
+        .iter()
+        .filter_map(|event| match event {
+            AssetEvent::Modified { handle } => Some(handle.clone()),
+            _ => None,
+        })
+        .collect();
+    for (entity, mut boat) in boat_query.iter_mut() {
+        if boat.fitted.as_ref() == Some(&boat.definition) && !modified.contains(&boat.definition) {
+            continue;
+        }
+        let definition = match definitions.get(&boat.definition) {
+            Some(definition) => definition,
+            None => continue,
+        };
+        info!("fitting boat definition {}", definition.name);
+        boat.fitted = Some(boat.definition.clone());
+        boat.hull = definition.buoyancy.clone();
+        boat.throttle = boat
+            .throttle
+            .clamp(-definition.max_thrust, definition.max_thrust);
+
+        let mut boat_commands = commands.entity(entity);
+        boat_commands.despawn_descendants();
+        boat_commands.with_children(|parent| {
+            parent
+                .spawn_bundle(PbrBundle {
+                    mesh: asset_server.load(&definition.hull.mesh),
+                    material: materials.add(definition.hull.material.material(&asset_server)),
+                    transform: definition.hull.transform(),
+                    ..Default::default()
+                })
+                .insert(Name::new("Hull"));
+            if let Some(jet) = &definition.jet {
+                // the jet swivels around its position, the mesh sits in it as it is
+                parent
+                    .spawn_bundle(PbrBundle {
+                        transform: Transform::from_translation(jet.position),
+                        ..Default::default()
+                    })
+                    .insert(BoatJet)
+                    .insert(Name::new("BoatJet"))
+                    .with_children(|parent| {
+                        parent.spawn_bundle(PbrBundle {
+                            mesh: asset_server.load(&jet.mesh),
+                            material: materials.add(jet.material.material(&asset_server)),
+                            transform: Transform {
+                                translation: Vec3::ZERO,
+                                ..jet.transform()
+                            },
+                            ..Default::default()
+                        });
+                    });
+            }
+            if let Some(sail) = &definition.sail {
+                // the sail swings with the boom around the mast, see sail_system
+                let mesh = meshes.add(sail::sail_mesh(sail.luff, sail.foot));
+                parent
+                    .spawn_bundle(PbrBundle {
+                        mesh: mesh.clone(),
+                        material: materials.add(sail.material.material(&asset_server)),
+                        transform: Transform::from_translation(sail.mast),
+                        ..Default::default()
+                    })
+                    .insert(BoatSail {
+                        mesh,
+                        luff: sail.luff,
+                        foot: sail.foot,
+                    })
+                    .insert(Name::new("Sail"));
+            }
+        });
+    }
+}
+
+// m/s the paddle pushes the water down at full throttle
+const PADDLE_SPLASH: f32 = 0.5;
+// a boat rolled over by a wave face across it, like a rogue wave caught on the beam, takes
+// this many seconds to be righted
+const RIGHTING_TIME: f32 = 8.;
 
-pub fn boat_physics_system(
+/// Turns the paddles with the steering and splashes them through the water.
+pub fn paddle_system(
     time: Res<Time>,
-    mut paddle_query: Query<(&mut BoatJet, &mut Transform), Without<PlayerBoat>>,
-    mut boat_query: Query<(&mut PlayerBoat, &mut Transform), Without<BoatJet>>,
-    water_query: Query<&Water>,
-    mut ev_move: EventWriter<MoveEvent>,
+    mut paddle_query: Query<(&BoatJet, &mut Transform), Without<PlayerBoat>>,
+    boat_query: Query<(&PlayerBoat, &Transform), Without<BoatJet>>,
+    mut ev_splash: EventWriter<SplashEvent>,
 ) {
-    if let Ok((mut boat, mut boat_transform)) = boat_query.get_single_mut() {
+    if let Ok((boat, boat_transform)) = boat_query.get_single() {
         let throttle_rotation = Quat::from_rotation_y(FRAC_PI_4 * boat.steer);
         for (_paddle, mut paddle_transform) in paddle_query.iter_mut() {
             paddle_transform.rotation = throttle_rotation;
+            if boat.throttle != 0. {
+                let paddle = boat_transform.mul_vec3(paddle_transform.translation);
+                ev_splash.send(SplashEvent {
+                    position: Vec2::new(paddle.x, paddle.z),
+                    strength: PADDLE_SPLASH * boat.throttle.abs() * time.delta_seconds(),
+                    radius: 0.3,
+                });
+            }
         }
-        boat.world_rotation += -boat.steer * time.delta_seconds();
+    }
+}
+
+pub fn boat_physics_system(
+    time: Res<Time>,
+    surface: WaterSurface,
+    definitions: Res<Assets<BoatDefinition>>,
+    mut boat_query: Query<(&mut PlayerBoat, &mut Transform)>,
+    water_query: Query<(&Water, &GlobalTransform)>,
+    wind: Res<Wind>,
+    mut ev_move: EventWriter<MoveEvent>,
+) {
+    if let Ok((mut boat, mut boat_transform)) = boat_query.get_single_mut() {
+        let definition = match definitions.get(&boat.definition) {
+            Some(definition) => definition,
+            None => return,
+        };
+        boat.world_rotation += -boat.steer * definition.turn_rate * time.delta_seconds();
 
         let world_rotation_quat = Quat::from_rotation_y(boat.world_rotation);
 
-        let propulsion = (world_rotation_quat * -Vec3::Z) * ENGINE_FORCE * boat.throttle;
-        let drag = -DRAG * boat.velocity * boat.speed;
-        let friction = -FRICTION * boat.velocity;
+        let heading = world_rotation_quat * -Vec3::Z;
+        let position = Vec2::new(boat_transform.translation.x, boat_transform.translation.z);
+        let drift = surface.current.at(position);
+        let drift = Vec3::new(drift.x, 0., drift.y);
+        let ground_velocity = boat.velocity + drift;
+        let apparent_wind = wind.at(position) - Vec2::new(ground_velocity.x, ground_velocity.z);
+        // the wind felt on board in the boat's own xz, the sail works in it
+        let local_wind =
+            world_rotation_quat.inverse() * Vec3::new(apparent_wind.x, 0., apparent_wind.y);
+        let local_wind = Vec2::new(local_wind.x, local_wind.z);
+        boat.rig.update(local_wind, time.delta_seconds());
+
+        // nothing drives a capsized boat, it drifts until it is righted
+        boat.capsized = (boat.capsized - time.delta_seconds()).max(0.);
+        let sail_push = match &definition.sail {
+            Some(sail) if boat.capsized == 0. => {
+                let push = boat.rig.force(sail.area(), local_wind);
+                boat.rig.heel_to(
+                    push.x,
+                    sail.center_of_effort(),
+                    definition.stability,
+                    time.delta_seconds(),
+                );
+                push
+            }
+            _ => {
+                boat.rig
+                    .heel_to(0., 1., definition.stability, time.delta_seconds());
+                Vec2::ZERO
+            }
+        };
+        // the keel takes the sail's push across the boat, only the push ahead drives it
+        let propulsion = if boat.capsized > 0. {
+            Vec3::ZERO
+        } else {
+            heading * (definition.engine_force * boat.throttle - sail_push.y)
+        };
+        // a planing hull rides up onto its bow wave and leaves most of the water behind
+        let resistance = definition
+            .planing
+            .as_ref()
+            .map_or(1., |planing| planing.resistance_at(boat.speed));
+        let drag = -definition.drag * resistance * boat.velocity * boat.speed;
+        let friction = -definition.friction * resistance * boat.velocity;
 
         let sum_force = propulsion + drag + friction;
-        let acceleration = sum_force / BOAT_MASS;
+        let acceleration = sum_force / definition.mass;
         boat.velocity = boat.velocity + (acceleration * time.delta_seconds());
         boat.speed = boat.velocity.length();
+        boat.speed_over_ground = (boat.velocity + drift).length();
 
-        let jump = boat.velocity * time.delta_seconds();
+        let jump = (boat.velocity + drift) * time.delta_seconds();
         let mut new_translation = boat_transform.translation + jump;
 
-        if let Ok(water) = water_query.get_single() {
-            let wavedata = water.wave_data_at_point(
-                Vec2::new(new_translation.x, new_translation.z),
-                time.seconds_since_startup() as f32 * water.wave_speed,
+        if let Ok((water, water_transform)) = water_query.get_single() {
+            let surfaces = water.wave_batch(&surface.clock).surface_at_points(
+                &boat.hull.world_points(new_translation, boat.world_rotation),
+                water::SURFACE_TOLERANCE,
             );
+            let pose = boat.hull.pose(new_translation, &surfaces);
             // let takeoff_speed = (boat.speed / 50.).clamp(0., 1.);
-            new_translation.y = wavedata.position.y; // * (1. - takeoff_speed) + takeoff_speed * 5.;
+            // the waves are around the water's level, which rises and falls with the tide
+            new_translation.y = water_transform.translation().y + pose.height; // * (1. - takeoff_speed) + takeoff_speed * 5.;
+
+            let roll = pose.normal.dot(world_rotation_quat * Vec3::X).asin();
+            // knocked down by a wave face, or by the wind in the sail
+            let capsize = definition.capsize_angle.to_radians();
+            if (roll.abs() > capsize || boat.rig.heel.abs() > capsize) && boat.capsized == 0. {
+                boat.capsized = RIGHTING_TIME;
+                boat.rig.heel = 0.;
+            }
+            let keel = if boat.capsized > 0. {
+                Quat::from_rotation_z(PI)
+            } else {
+                Quat::IDENTITY
+            };
 
-            let normal_quat = water::surface_quat(&wavedata);
+            let normal_quat = water::surface_quat(pose.normal);
             boat_transform.rotation = boat_transform.rotation.slerp(
                 // normal_quat.lerp(Quat::IDENTITY, takeoff_speed)
-                normal_quat * world_rotation_quat,
+                normal_quat * world_rotation_quat * keel * Quat::from_rotation_z(boat.rig.heel),
                 // * Quat::from_rotation_z(
                 // FRAC_PI_4 * -boat.steer * (boat.speed / 100.).clamp(0., 1.),
                 // ), // bank
@@ -0,0 +1,226 @@
+use crate::water::hull::Hull;
+use bevy::asset::{AssetLoader, BoxedFuture, Error, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+/// How a boat looks and handles, read from a `.boat.ron` file in assets/boats. The asset
+/// server watches the files, saving one changes the boat on the water right away.
+#[derive(Debug, Clone, Deserialize, TypeUuid)]
+#[uuid = "0f6c2a43-5b7e-4d0a-9a51-3c8e2d7b9e14"]
+pub struct BoatDefinition {
+    pub name: String,
+    pub hull: Part,
+    /// the paddle or jet, it swivels with the steering
+    pub jet: Option<Part>,
+    pub sail: Option<Sail>,
+    /// kg
+    pub mass: f32,
+    /// resistance growing with the square of the speed through the water
+    pub drag: f32,
+    /// resistance growing with the speed through the water
+    pub friction: f32,
+    /// N of push at full throttle
+    pub engine_force: f32,
+    /// throttle reached holding ahead, astern it goes half as far
+    pub max_thrust: f32,
+    /// throttle gained per second
+    pub throttle_rate: f32,
+    /// steering gained per second, from amidships to full lock at 1
+    pub steer_rate: f32,
+    /// throttle and steering lost per second once let go
+    pub input_decay: f32,
+    /// radians per second the boat turns at full lock
+    pub turn_rate: f32,
+    /// degrees a wave face across the boat, or the wind in its sail, may roll it over
+    /// before it capsizes
+    pub capsize_angle: f32,
+    /// N·m the hull pushes back with for each radian it heels
+    pub stability: f32,
+    /// a hull that rides up onto its bow wave once fast enough
+    pub planing: Option<Planing>,
+    /// m the camera follows behind
+    pub camera_distance: f32,
+    /// where the water is sampled for buoyancy, with -z towards the bow
+    pub buoyancy: Hull,
+}
+
+/// A mesh of the boat and where it sits.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Part {
+    /// asset path of the mesh, like "raft.glb#Mesh0/Primitive0"
+    pub mesh: String,
+    #[serde(default)]
+    pub material: PartMaterial,
+    /// m from the boat's origin, in its own space
+    #[serde(default)]
+    pub position: Vec3,
+    #[serde(default)]
+    pub rotation: Quat,
+    #[serde(default = "unscaled")]
+    pub scale: Vec3,
+}
+
+fn unscaled() -> Vec3 {
+    Vec3::ONE
+}
+
+impl Part {
+    pub fn transform(&self) -> Transform {
+        Transform {
+            translation: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PartMaterial {
+    pub color: Color,
+    /// asset path of the base color texture
+    pub texture: Option<String>,
+    pub metallic: f32,
+    pub reflectance: f32,
+    pub double_sided: bool,
+}
+
+impl Default for PartMaterial {
+    fn default() -> Self {
+        let material = StandardMaterial::default();
+        PartMaterial {
+            color: material.base_color,
+            texture: None,
+            metallic: material.metallic,
+            reflectance: material.reflectance,
+            double_sided: material.double_sided,
+        }
+    }
+}
+
+impl PartMaterial {
+    pub fn material(&self, asset_server: &AssetServer) -> StandardMaterial {
+        StandardMaterial {
+            base_color: self.color,
+            base_color_texture: self.texture.as_ref().map(|path| asset_server.load(path)),
+            metallic: self.metallic,
+            reflectance: self.reflectance,
+            double_sided: self.double_sided,
+            ..Default::default()
+        }
+    }
+}
+
+/// A square sail, hoisted up the mast and sheeted along the boom. Its mesh is made to
+/// billow, see sail.rs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sail {
+    #[serde(default)]
+    pub material: PartMaterial,
+    /// where the boom meets the mast, from the boat's origin in its own space
+    pub mast: Vec3,
+    /// m up the mast
+    pub luff: f32,
+    /// m along the boom
+    pub foot: f32,
+}
+
+impl Sail {
+    /// m² of canvas
+    pub fn area(&self) -> f32 {
+        self.luff * self.foot
+    }
+
+    /// m above the waterline the wind pushes on the sail on the whole.
+    pub fn center_of_effort(&self) -> f32 {
+        self.mast.y + self.luff / 2.
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Planing {
+    /// m/s through the water it starts to rise out of it at, and is fully up at twice that
+    pub speed: f32,
+    /// share of the drag and friction left once it is up
+    pub resistance: f32,
+}
+
+impl Planing {
+    /// Share of the drag and friction left going at `speed`.
+    pub fn resistance_at(&self, speed: f32) -> f32 {
+        let lift = ((speed - self.speed) / self.speed).clamp(0., 1.);
+        1. + (self.resistance - 1.) * lift
+    }
+}
+
+#[derive(Default)]
+pub struct BoatDefinitionLoader;
+
+impl AssetLoader for BoatDefinitionLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), Error>> {
+        Box::pin(async move {
+            let definition: BoatDefinition = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(definition));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["boat.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raft_definition_loads() {
+        let raft: BoatDefinition =
+            ron::de::from_str(include_str!("../assets/boats/raft.boat.ron")).unwrap();
+        assert_eq!(raft.hull.mesh, "raft.glb#Mesh0/Primitive0");
+        assert_eq!(raft.buoyancy.points.len(), 9);
+        assert!((raft.buoyancy.size() - Vec2::new(3.8, 4.4)).length() < 1e-5);
+        assert!((raft.sail.unwrap().area() - 9.).abs() < 0.1);
+        assert!(raft.planing.is_none());
+    }
+
+    #[test]
+    fn motor_boat_definitions_load() {
+        for definition in [
+            include_str!("../assets/boats/correolas.boat.ron"),
+            include_str!("../assets/boats/lancha.boat.ron"),
+        ] {
+            let boat: BoatDefinition = ron::de::from_str(definition).unwrap();
+            assert!(boat.jet.is_some(), "{}", boat.name);
+            assert!(boat.sail.is_none(), "{}", boat.name);
+            assert!(boat.buoyancy.points.len() >= 3, "{}", boat.name);
+        }
+    }
+
+    #[test]
+    fn sailing_boat_definition_loads() {
+        let barquica: BoatDefinition =
+            ron::de::from_str(include_str!("../assets/boats/barquica.boat.ron")).unwrap();
+        assert!(barquica.sail.is_some());
+        // and a weak outboard
+        assert!(barquica.jet.is_some());
+        assert!(barquica.buoyancy.points.len() >= 3);
+    }
+
+    #[test]
+    fn planing_eases_resistance_above_its_speed() {
+        let planing = Planing {
+            speed: 10.,
+            resistance: 0.4,
+        };
+        assert_eq!(planing.resistance_at(5.), 1.);
+        assert!((planing.resistance_at(15.) - 0.7).abs() < 1e-6);
+        assert!((planing.resistance_at(30.) - 0.4).abs() < 1e-6);
+    }
+}
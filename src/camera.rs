@@ -1,7 +1,10 @@
 use crate::boat::PlayerBoat;
+use crate::boat_definition::BoatDefinition;
 use crate::sky::SkyDomeLayerBg;
+use crate::water::reflection::UNREFLECTED_LAYER;
 use crate::water::WaterCamera;
 use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
 
 #[derive(Component)]
 pub struct CameraTracker {
@@ -42,6 +45,7 @@ pub fn camera_startup_system(mut commands: Commands) {
             looking_up: LookingUp::None,
             input_rotation: Quat::IDENTITY,
         })
+        .insert(RenderLayers::from_layers(&[0, UNREFLECTED_LAYER]))
         .insert(WaterCamera);
 }
 
@@ -58,9 +62,15 @@ pub fn camera_system(
         (Without<PlayerBoat>, Without<CameraTracker>),
     >,
     mut boat_query: Query<(&mut PlayerBoat, &mut Transform), Without<CameraTracker>>,
+    definitions: Res<Assets<BoatDefinition>>,
 ) {
     if let Ok((mut camera, mut camera_transform)) = camera_query.get_single_mut() {
+        // bigger boats are followed from further back
+        let mut distance = CAMERA_Z_TRANSLATION;
         if let Ok((boat, boat_transform)) = boat_query.get_single_mut() {
+            if let Some(definition) = definitions.get(&boat.definition) {
+                distance = definition.camera_distance;
+            }
             camera.bobber.translation.x = boat_transform.translation.x;
             camera.bobber.translation.z = boat_transform.translation.z;
             // camera.bobber.translation.y = 15. + camera.looking_up.value() * 100.;
@@ -71,8 +81,7 @@ pub fn camera_system(
             );
         }
 
-        let camera_z =
-            CAMERA_Z_TRANSLATION - (camera.looking_up.value() * (CAMERA_Z_TRANSLATION - 0.01));
+        let camera_z = distance - (camera.looking_up.value() * (distance - 0.01));
 
         camera_transform.translation =
             camera.bobber.translation + (camera.bobber.rotation * Vec3::new(0.0, -5.0, camera_z));
@@ -1,20 +1,24 @@
 use crate::boat;
+use crate::boat_definition::BoatDefinition;
 use crate::camera::{CameraTracker, LookingUp};
+use crate::water::{fft::FftOcean, Water, WaterQuality, WaveBackend, Weather};
 use crate::AppState;
 use bevy::{input::mouse::MouseMotion, prelude::*};
 // use bevy_inspector_egui::WorldInspectorParams;
 
-const INPUT_ACCEL: f32 = 1.0;
-const INPUT_DECAY: f32 = 4.0;
-const STEER_ACCEL: f32 = 10.0;
-const BOAT_MAX_THRUST: f32 = 1.0;
+// share of the sheet hauled in or eased out per second
+const SHEET_RATE: f32 = 0.5;
 
 pub fn add_systems(app: &mut bevy::prelude::App) -> &mut bevy::prelude::App {
     app.add_system(bevy::window::close_on_esc)
         .add_system_set(
             SystemSet::on_update(AppState::InGame)
                 .with_system(ingame_keyboard_input_system.label("input"))
-                .with_system(mouse_input_system.label("input")),
+                .with_system(mouse_input_system.label("input"))
+                .with_system(boat_change_input_system.label("input"))
+                .with_system(sheet_input_system.label("input"))
+                .with_system(water_backend_input_system.label("input"))
+                .with_system(water_quality_input_system.label("input")),
         )
         .add_system_set(
             SystemSet::on_update(AppState::Menu)
@@ -25,6 +29,7 @@ pub fn add_systems(app: &mut bevy::prelude::App) -> &mut bevy::prelude::App {
 pub fn ingame_keyboard_input_system(
     time: Res<Time>,
     mut keyboard_input: ResMut<Input<KeyCode>>,
+    definitions: Res<Assets<BoatDefinition>>,
     mut boat_query: Query<&mut boat::PlayerBoat>,
     mut camera_query: Query<(&mut Transform, &mut CameraTracker)>,
     mut state: ResMut<State<AppState>>,
@@ -47,36 +52,42 @@ pub fn ingame_keyboard_input_system(
     // }
 
     for mut boat in &mut boat_query.iter_mut() {
+        let definition = match definitions.get(&boat.definition) {
+            Some(definition) => definition,
+            None => continue,
+        };
         if keyboard_input.pressed(KeyCode::W) {
-            if boat.throttle < BOAT_MAX_THRUST {
-                boat.throttle =
-                    (boat.throttle + INPUT_ACCEL * time.delta_seconds()).min(BOAT_MAX_THRUST);
+            if boat.throttle < definition.max_thrust {
+                boat.throttle = (boat.throttle + definition.throttle_rate * time.delta_seconds())
+                    .min(definition.max_thrust);
             }
         } else if boat.throttle > 0.0 {
-            boat.throttle = (boat.throttle - INPUT_DECAY * time.delta_seconds()).max(0.0);
+            boat.throttle =
+                (boat.throttle - definition.input_decay * time.delta_seconds()).max(0.0);
         }
         if keyboard_input.pressed(KeyCode::S) {
-            if boat.throttle > -BOAT_MAX_THRUST / 2. {
-                boat.throttle =
-                    (boat.throttle - INPUT_ACCEL * time.delta_seconds()).max(-BOAT_MAX_THRUST);
+            if boat.throttle > -definition.max_thrust / 2. {
+                boat.throttle = (boat.throttle - definition.throttle_rate * time.delta_seconds())
+                    .max(-definition.max_thrust);
             }
         } else if boat.throttle < 0.0 {
-            boat.throttle = (boat.throttle + INPUT_DECAY * time.delta_seconds()).max(0.0);
+            boat.throttle =
+                (boat.throttle + definition.input_decay * time.delta_seconds()).max(0.0);
         }
 
         if keyboard_input.pressed(KeyCode::A) {
             if boat.steer > -1.0 {
-                boat.steer = (boat.steer - STEER_ACCEL * time.delta_seconds()).max(-1.0);
+                boat.steer = (boat.steer - definition.steer_rate * time.delta_seconds()).max(-1.0);
             }
         } else if boat.steer < 0.0 {
-            boat.steer = (boat.steer + INPUT_DECAY * time.delta_seconds()).min(0.0);
+            boat.steer = (boat.steer + definition.input_decay * time.delta_seconds()).min(0.0);
         }
         if keyboard_input.pressed(KeyCode::D) {
             if boat.steer < 1.0 {
-                boat.steer = (boat.steer + STEER_ACCEL * time.delta_seconds()).min(1.0);
+                boat.steer = (boat.steer + definition.steer_rate * time.delta_seconds()).min(1.0);
             }
         } else if boat.steer > 0.0 {
-            boat.steer = (boat.steer - INPUT_DECAY * time.delta_seconds()).max(0.0);
+            boat.steer = (boat.steer - definition.input_decay * time.delta_seconds()).max(0.0);
         }
 
         if keyboard_input.just_pressed(KeyCode::Space) {
@@ -91,6 +102,85 @@ pub fn ingame_keyboard_input_system(
     }
 }
 
+// B changes boats for the next of the fleet while moored at an island
+pub fn boat_change_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    fleet: Res<boat::Fleet>,
+    mut boat_query: Query<&mut boat::PlayerBoat>,
+) {
+    if keyboard_input.just_pressed(KeyCode::B) {
+        for mut boat in boat_query.iter_mut() {
+            if boat.moored {
+                boat.definition = fleet.next(&boat.definition);
+            }
+        }
+    }
+}
+
+// Z hauls the sheet in and X eases it out, T leaves the trim to the crew
+pub fn sheet_input_system(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut boat_query: Query<&mut boat::PlayerBoat>,
+) {
+    for mut boat in boat_query.iter_mut() {
+        if keyboard_input.just_pressed(KeyCode::T) {
+            boat.rig.auto_trim = !boat.rig.auto_trim;
+        }
+        let mut sheet = boat.rig.sheet;
+        if keyboard_input.pressed(KeyCode::Z) {
+            sheet -= SHEET_RATE * time.delta_seconds();
+            boat.rig.auto_trim = false;
+        }
+        if keyboard_input.pressed(KeyCode::X) {
+            sheet += SHEET_RATE * time.delta_seconds();
+            boat.rig.auto_trim = false;
+        }
+        boat.rig.sheet = sheet.clamp(0., 1.);
+    }
+}
+
+const FFT_SIZE: usize = 64;
+const FFT_LENGTH: f32 = 256.;
+
+// F switches between summed Gerstner waves and the FFT ocean, out at sea only
+pub fn water_backend_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    weather: Res<Weather>,
+    mut water_query: Query<&mut Water>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F) {
+        for mut water in water_query.iter_mut() {
+            // the FFT tile knows nothing of the shore, see WaveBackend::Fft
+            if !water.shore.shoals.is_empty() {
+                continue;
+            }
+            water.backend = match water.backend {
+                WaveBackend::Gerstner => WaveBackend::Fft(Box::new(FftOcean::new(
+                    &weather.sea_state,
+                    FFT_SIZE,
+                    FFT_LENGTH,
+                ))),
+                WaveBackend::Fft(_) => WaveBackend::Gerstner,
+            };
+        }
+    }
+}
+
+// Q cycles through the water mesh resolutions
+pub fn water_quality_input_system(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut quality: ResMut<WaterQuality>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Q) {
+        *quality = match *quality {
+            WaterQuality::Low => WaterQuality::Medium,
+            WaterQuality::Medium => WaterQuality::High,
+            WaterQuality::High => WaterQuality::Low,
+        };
+    }
+}
+
 pub fn menu_keyboard_input_system(
     mut keyboard_input: ResMut<Input<KeyCode>>,
     mut state: ResMut<State<AppState>>,
@@ -1,9 +1,11 @@
 extern crate lazy_static_include;
-use bevy::{pbr::AmbientLight, prelude::*};
+use bevy::{asset::AssetServerSettings, pbr::AmbientLight, prelude::*};
 
 mod boat;
+mod boat_definition;
 mod camera;
 mod input;
+mod sail;
 mod sky;
 mod ui;
 mod water;
@@ -32,6 +34,30 @@ pub enum Island {
     IslandB,
 }
 
+impl Island {
+    /// The sea floor around the island. Its shallows end well within the 700m the boat
+    /// gets from it before the island is left behind.
+    pub fn bathymetry(&self) -> water::shore::Bathymetry {
+        match self {
+            Island::Home => water::shore::Bathymetry {
+                radius: 30.,
+                slope: 0.05,
+                depth: 25.,
+            },
+            Island::IslandA => water::shore::Bathymetry {
+                radius: 20.,
+                slope: 0.08,
+                depth: 30.,
+            },
+            Island::IslandB => water::shore::Bathymetry {
+                radius: 25.,
+                slope: 0.04,
+                depth: 20.,
+            },
+        }
+    }
+}
+
 fn main() {
     let mut app = App::new();
     app.insert_resource(WindowDescriptor {
@@ -44,6 +70,11 @@ fn main() {
     });
 
     app.insert_resource(Msaa { samples: 4 });
+    // boat definitions reload as they are saved
+    app.insert_resource(AssetServerSettings {
+        watch_for_changes: true,
+        ..Default::default()
+    });
     app.add_plugins(DefaultPlugins);
     // app.add_plugin(WorldInspectorPlugin::new());
     // let mut registry = app
@@ -59,6 +90,9 @@ fn main() {
     app.add_state(AppState::InGame);
     app.add_event::<NavigationEvent>();
     app.add_event::<boat::MoveEvent>();
+    app.add_event::<water::weather::WeatherEvent>();
+    app.add_event::<water::rogue::RogueWaveEvent>();
+    app.add_event::<water::ripples::SplashEvent>();
 
     app.insert_resource(InGameState {
         time: DayTime::Night,
@@ -69,6 +103,8 @@ fn main() {
     input::add_systems(&mut app);
 
     app.add_system(island_enter_leave);
+    app.add_system(sun_system.after("physics"));
+    app.add_system(mooring_system.after("physics"));
 
     boat::add_systems(&mut app);
     sky::add_systems(&mut app);
@@ -96,7 +132,11 @@ fn setup(
             ..Default::default()
         })
         .insert(Name::new("Flotante1"))
-        .insert(water::Swimmer::default());
+        .insert(water::Swimmer {
+            hull: water::hull::Hull::rectangle(6.4, 1.7, 2.),
+            mass: 6000.,
+            ..Default::default()
+        });
 
     commands
         .spawn_bundle(PbrBundle {
@@ -118,7 +158,11 @@ fn setup(
             ..Default::default()
         })
         .insert(Name::new("Flotante3"))
-        .insert(water::Swimmer::default());
+        .insert(water::Swimmer {
+            hull: water::hull::Hull::rectangle(0.5, 0.5, 0.5),
+            mass: 40.,
+            ..Default::default()
+        });
 
     let theta = std::f32::consts::FRAC_PI_4;
     let light_transform = Mat4::from_euler(EulerRot::ZYX, 0.0, std::f32::consts::FRAC_PI_2, -theta);
@@ -142,6 +186,38 @@ fn setup(
     });
 }
 
+/// Keeps the sun over the boat, its shadows only reach 10m around it.
+fn sun_system(
+    boat_query: Query<&Transform, (With<boat::PlayerBoat>, Without<DirectionalLight>)>,
+    mut light_query: Query<&mut Transform, With<DirectionalLight>>,
+) {
+    if let Ok(boat_transform) = boat_query.get_single() {
+        for mut light_transform in light_query.iter_mut() {
+            light_transform.translation = boat_transform.translation;
+        }
+    }
+}
+
+/// m off an island's beach a boat is moored there.
+const MOORING_DISTANCE: f32 = 40.;
+
+/// Moors the boat while it is close to an island, where it can be changed for another of
+/// the fleet.
+fn mooring_system(
+    island_query: Query<(&WorldIsland, &Transform), Without<boat::PlayerBoat>>,
+    mut boat_query: Query<(&mut boat::PlayerBoat, &Transform)>,
+) {
+    for (mut boat, boat_transform) in boat_query.iter_mut() {
+        let moored = island_query.iter().any(|(island, island_transform)| {
+            let offset = boat_transform.translation - island_transform.translation;
+            Vec2::new(offset.x, offset.z).length() < island.bathymetry.radius + MOORING_DISTANCE
+        });
+        if boat.moored != moored {
+            boat.moored = moored;
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum NavigationEvent {
     Enter(Island, Quat, Vec3),
@@ -154,6 +230,8 @@ pub struct WorldIsland {
     #[allow(dead_code)]
     island: Island,
     sky_rotation: Quat,
+    /// shallows the waves break on
+    bathymetry: water::shore::Bathymetry,
 }
 
 fn island_enter_leave(
@@ -187,6 +265,7 @@ fn island_enter_leave(
                         .insert(WorldIsland {
                             island: *island,
                             sky_rotation: *sky_rotation,
+                            bathymetry: island.bathymetry(),
                         })
                         .with_children(|parent| {
                             parent.spawn_bundle(SceneBundle {
@@ -0,0 +1,370 @@
+use crate::boat::PlayerBoat;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+const AIR_DENSITY: f32 = 1.2;
+/// A sail can not drive closer to the wind than this, it just flaps.
+pub const NO_GO_ANGLE: f32 = 40. * PI / 180.;
+/// Angle of attack of the most lift, past it the flow breaks away from the sail.
+const STALL_ANGLE: f32 = 20. * PI / 180.;
+const MAX_LIFT: f32 = 1.4;
+/// Drag of the sail edge on to the wind, and added square on to it.
+const MIN_DRAG: f32 = 0.05;
+const MAX_DRAG: f32 = 1.3;
+/// Drag of a flapping sail.
+const FLAP_DRAG: f32 = 0.15;
+/// Below this angle of attack the luff lifts and the sail starts to flap.
+const LUFF_ANGLE: f32 = 5. * PI / 180.;
+/// The shrouds stop the boom this far off the centreline.
+const MAX_BOOM: f32 = 85. * PI / 180.;
+/// Radians per second the boom swings with the wind, and slams across in a gybe.
+const BOOM_RATE: f32 = 1.5;
+const GYBE_RATE: f32 = 5.;
+/// Radians a gybe throws the boat over on to its new side.
+const GYBE_HEEL: f32 = 8. * PI / 180.;
+/// How fast the boat heels to the wind's push, per second.
+const HEEL_RESPONSE: f32 = 2.;
+/// How much the sail bellies out full of wind, as a share of its foot.
+const BILLOW: f32 = 0.08;
+/// Vertices up and along the sail.
+const SAIL_SEGMENTS: u32 = 8;
+
+/// How the sail is set and how it draws.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rig {
+    /// 0 hauled hard in to 1 let right out
+    pub sheet: f32,
+    /// sheet kept at the best trim for the wind on its own
+    pub auto_trim: bool,
+    /// radians the boom stands off the centreline, positive to starboard
+    pub boom: f32,
+    /// 0 flapping to 1 full of wind
+    pub fill: f32,
+    /// radians the boat leans over, positive with the starboard side up
+    pub heel: f32,
+    /// radians off the bow the apparent wind came from at the last update, see `wind_angle`
+    pub wind_angle: f32,
+}
+
+/// Points of sail, by the angle of the apparent wind off the bow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PointOfSail {
+    InIrons,
+    CloseHauled,
+    CloseReach,
+    BeamReach,
+    BroadReach,
+    Running,
+}
+
+impl PointOfSail {
+    pub fn from_angle(angle: f32) -> Self {
+        let degrees = angle.abs().to_degrees();
+        if angle.abs() < NO_GO_ANGLE {
+            PointOfSail::InIrons
+        } else if degrees < 60. {
+            PointOfSail::CloseHauled
+        } else if degrees < 80. {
+            PointOfSail::CloseReach
+        } else if degrees < 110. {
+            PointOfSail::BeamReach
+        } else if degrees < 160. {
+            PointOfSail::BroadReach
+        } else {
+            PointOfSail::Running
+        }
+    }
+}
+
+/// Lift of the sail over its angle of attack: rising until it stalls, then only the
+/// push of a flat plate, gone once it stands square to the wind.
+pub fn lift_coefficient(attack: f32) -> f32 {
+    if attack < STALL_ANGLE {
+        MAX_LIFT * attack / STALL_ANGLE
+    } else {
+        0.9 * (2. * attack.min(FRAC_PI_2)).sin()
+    }
+}
+
+/// Drag of the sail over its angle of attack, most with it square to the wind.
+pub fn drag_coefficient(attack: f32) -> f32 {
+    MIN_DRAG + MAX_DRAG * attack.sin().powi(2)
+}
+
+/// Angle of `apparent_wind`, the way the air flows over the boat in its own xz, off the
+/// bow: 0 with the wind right on the bow, PI from right astern, positive from starboard.
+pub fn wind_angle(apparent_wind: Vec2) -> f32 {
+    // the wind comes from the opposite of where it flows, the bow is -z
+    let from = -apparent_wind;
+    from.x.atan2(-from.y)
+}
+
+impl Rig {
+    /// Boom angle off the centreline for the best lift with the wind at `angle`.
+    fn best_boom(angle: f32) -> f32 {
+        (angle.abs() - STALL_ANGLE * 0.9).clamp(0., MAX_BOOM)
+    }
+
+    /// Sheet for the best lift with the wind at `angle`.
+    pub fn best_sheet(angle: f32) -> f32 {
+        Self::best_boom(angle) / MAX_BOOM
+    }
+
+    /// Swings the boom over `delta` seconds with the `apparent_wind` in the boat's own xz.
+    /// It goes out to leeward as far as the sheet lets it, and crosses over when the bow
+    /// or the stern passes through the wind: gently when tacking, slamming across and
+    /// throwing the boat over when gybing.
+    pub fn update(&mut self, apparent_wind: Vec2, delta: f32) {
+        let angle = wind_angle(apparent_wind);
+        self.wind_angle = angle;
+        if self.auto_trim {
+            self.sheet = Self::best_sheet(angle);
+        }
+        // with the wind from starboard the boom goes out to port
+        let leeward = -angle.signum();
+        let target = leeward * (self.sheet * MAX_BOOM).min(angle.abs());
+        let crossing = self.boom != 0. && self.boom.signum() != target.signum();
+        let rate = if crossing && angle.abs() > FRAC_PI_2 {
+            GYBE_RATE
+        } else {
+            BOOM_RATE
+        };
+        let boom = self.boom + (target - self.boom).clamp(-rate * delta, rate * delta);
+        if crossing && boom.signum() != self.boom.signum() && angle.abs() > FRAC_PI_2 {
+            self.heel += leeward * -GYBE_HEEL;
+        }
+        self.boom = boom;
+        self.fill = self.filling(apparent_wind);
+    }
+
+    /// Angle between the sail and the wind, the sail runs aft from the mast along the boom.
+    pub fn attack(&self, apparent_wind: Vec2) -> f32 {
+        let chord = Vec2::new(self.boom.sin(), self.boom.cos());
+        chord.angle_between(apparent_wind).abs()
+    }
+
+    fn filling(&self, apparent_wind: Vec2) -> f32 {
+        let angle = wind_angle(apparent_wind);
+        // aback with the boom still to windward, and no drive at all in irons
+        let aback = self.boom != 0. && self.boom.signum() == angle.signum();
+        if aback || angle.abs() < NO_GO_ANGLE || apparent_wind == Vec2::ZERO {
+            return 0.;
+        }
+        (self.attack(apparent_wind) / LUFF_ANGLE).clamp(0., 1.)
+    }
+
+    /// Push of a sail of `area` m² in the boat's own xz with the `apparent_wind` in it: lift
+    /// across the wind and drag along it as far as the sail fills, the drag of a flapping
+    /// sail for the rest.
+    pub fn force(&self, area: f32, apparent_wind: Vec2) -> Vec2 {
+        let speed = apparent_wind.length();
+        if speed == 0. {
+            return Vec2::ZERO;
+        }
+        let flow = apparent_wind / speed;
+        let chord = Vec2::new(self.boom.sin(), self.boom.cos());
+        // lift pulls across the wind towards the sail's lee side
+        let mut lift = flow.perp();
+        if lift.dot(-chord) < 0. {
+            lift = -lift;
+        }
+        let attack = self.attack(apparent_wind).min(FRAC_PI_2);
+        let pressure = 0.5 * AIR_DENSITY * area * speed * speed;
+        let drawing = lift * lift_coefficient(attack) + flow * drag_coefficient(attack);
+        pressure * (drawing * self.fill + flow * FLAP_DRAG * (1. - self.fill))
+    }
+
+    /// Leans the boat over `delta` seconds towards where the sail's `side_force` along its
+    /// x, `height` m above the waterline, balances the hull's `stability` in N·m a radian.
+    pub fn heel_to(&mut self, side_force: f32, height: f32, stability: f32, delta: f32) {
+        let target = -side_force * height / stability;
+        self.heel += (target - self.heel) * (HEEL_RESPONSE * delta).min(1.);
+    }
+}
+
+/// The sail, turned with the boom around the mast.
+#[derive(Component)]
+pub struct BoatSail {
+    pub mesh: Handle<Mesh>,
+    /// m up the mast
+    pub luff: f32,
+    /// m along the boom
+    pub foot: f32,
+}
+
+/// Points of a `luff` m tall and `foot` m long sail, with the mast at the origin and
+/// the boom along +z, bellied out along x by `billow` m in the middle.
+fn sail_points(luff: f32, foot: f32, billow: f32) -> (Vec<[f32; 3]>, Vec<[f32; 3]>) {
+    let mut positions = vec![];
+    let mut normals = vec![];
+    for j in 0..=SAIL_SEGMENTS {
+        for i in 0..=SAIL_SEGMENTS {
+            let (u, v) = (
+                i as f32 / SAIL_SEGMENTS as f32,
+                j as f32 / SAIL_SEGMENTS as f32,
+            );
+            let belly = (PI * u).sin() * (PI * v).sin();
+            positions.push([billow * belly, v * luff, u * foot]);
+            let dz = billow * PI / foot * (PI * u).cos() * (PI * v).sin();
+            let dy = billow * PI / luff * (PI * u).sin() * (PI * v).cos();
+            normals.push(Vec3::new(1., -dy, -dz).normalize().to_array());
+        }
+    }
+    (positions, normals)
+}
+
+pub fn sail_mesh(luff: f32, foot: f32) -> Mesh {
+    let (positions, normals) = sail_points(luff, foot, 0.);
+    let uvs: Vec<[f32; 2]> = (0..=SAIL_SEGMENTS)
+        .flat_map(|j| {
+            (0..=SAIL_SEGMENTS).map(move |i| {
+                [
+                    i as f32 / SAIL_SEGMENTS as f32,
+                    1. - j as f32 / SAIL_SEGMENTS as f32,
+                ]
+            })
+        })
+        .collect();
+    let row = SAIL_SEGMENTS + 1;
+    let mut indices = vec![];
+    for j in 0..SAIL_SEGMENTS {
+        for i in 0..SAIL_SEGMENTS {
+            let corner = j * row + i;
+            indices.extend([corner, corner + 1, corner + row]);
+            indices.extend([corner + 1, corner + row + 1, corner + row]);
+        }
+    }
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh
+}
+
+/// Swings the sail out with the boom and bellies it out to leeward as it fills, a
+/// flapping sail shivers.
+pub fn sail_system(
+    time: Res<Time>,
+    boat_query: Query<&PlayerBoat>,
+    mut sail_query: Query<(&BoatSail, &mut Transform)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let rig = match boat_query.get_single() {
+        Ok(boat) => boat.rig,
+        Err(_) => return,
+    };
+    let flutter = (time.seconds_since_startup() as f32 * 14.).sin() * 0.3;
+    for (sail, mut transform) in sail_query.iter_mut() {
+        transform.rotation = Quat::from_rotation_y(rig.boom);
+        let side = if rig.boom < 0. { -1. } else { 1. };
+        let billow = sail.foot * BILLOW * (side * rig.fill + flutter * (1. - rig.fill));
+        if let Some(mesh) = meshes.get_mut(&sail.mesh) {
+            let (positions, normals) = sail_points(sail.luff, sail.foot, billow);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Apparent wind in the boat's xz coming from `degrees` off the bow, to starboard.
+    fn wind_from(degrees: f32) -> Vec2 {
+        let angle = degrees.to_radians();
+        -Vec2::new(angle.sin(), -angle.cos()) * 10.
+    }
+
+    /// Forward push of a trimmed 10m² sail once it has settled.
+    fn drive(rig: &mut Rig, wind: Vec2) -> f32 {
+        for _ in 0..200 {
+            rig.update(wind, 0.05);
+        }
+        -rig.force(10., wind).y
+    }
+
+    fn trimmed() -> Rig {
+        Rig {
+            auto_trim: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn wind_angle_gives_the_point_of_sail() {
+        assert!((wind_angle(wind_from(90.)) - FRAC_PI_2).abs() < 1e-5);
+        assert_eq!(
+            PointOfSail::from_angle(wind_angle(wind_from(-120.))),
+            PointOfSail::BroadReach
+        );
+        assert_eq!(
+            PointOfSail::from_angle(wind_angle(wind_from(-30.))),
+            PointOfSail::InIrons
+        );
+    }
+
+    #[test]
+    fn sail_flaps_in_the_no_go_zone() {
+        let edge = NO_GO_ANGLE.to_degrees();
+        for degrees in [0., 20., -20., edge - 1., 1. - edge] {
+            let mut rig = trimmed();
+            let wind = wind_from(degrees);
+            // only holds the boat back, straight down the wind
+            assert!(drive(&mut rig, wind) < 0., "{}", degrees);
+            assert_eq!(rig.fill, 0., "{}", degrees);
+            assert!(
+                rig.force(10., wind).perp_dot(wind).abs() < 1e-3,
+                "{}",
+                degrees
+            );
+        }
+        for degrees in [edge + 5., -edge - 5.] {
+            let mut rig = trimmed();
+            assert!(drive(&mut rig, wind_from(degrees)) > 0., "{}", degrees);
+            assert!(rig.fill > 0., "{}", degrees);
+        }
+    }
+
+    #[test]
+    fn sail_drives_best_on_a_reach() {
+        let mut rig = trimmed();
+        // the boom goes out to port with the wind from starboard, lift drives on a reach
+        // and drag before the wind
+        let reach = drive(&mut rig, wind_from(90.));
+        assert!(rig.boom < 0. && rig.fill == 1.);
+        let close_hauled = drive(&mut rig, wind_from(50.));
+        assert!(reach > close_hauled && close_hauled > 0.);
+        assert!(drive(&mut rig, wind_from(180.)) > 0.);
+    }
+
+    #[test]
+    fn sail_stalls_sheeted_hard_in() {
+        let reach = drive(&mut trimmed(), wind_from(100.));
+        let mut rig = Rig::default();
+        assert!(drive(&mut rig, wind_from(100.)) < 0.5 * reach);
+    }
+
+    #[test]
+    fn gybe_slams_the_boom_across_and_heels_to_leeward() {
+        let mut rig = trimmed();
+        drive(&mut rig, wind_from(170.));
+        assert!(rig.boom < 0.);
+        let heel = rig.heel;
+
+        // the wind comes round to port, the boom slams across to starboard
+        let delta = 0.05;
+        let boom = rig.boom;
+        rig.update(wind_from(-170.), delta);
+        assert!((rig.boom - boom - GYBE_RATE * delta).abs() < 1e-5);
+        assert_eq!(rig.heel, heel);
+        for _ in 0..40 {
+            rig.update(wind_from(-170.), delta);
+        }
+        assert!(rig.boom > 0.);
+        // and throws the boat over once on to its new leeward side, starboard side down
+        assert!((rig.heel - (heel - GYBE_HEEL)).abs() < 1e-5);
+    }
+}
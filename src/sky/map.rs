@@ -0,0 +1,122 @@
+use super::mesh::{StarDef, STAR_DISTANCE};
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use std::f32::consts::{PI, TAU};
+
+/// Texels around the horizon of the sky map, it is half as tall.
+const MAP_WIDTH: usize = 1024;
+const MAP_HEIGHT: usize = MAP_WIDTH / 2;
+/// Same as the stars get in sky.wgsl.
+const STAR_BRIGHTNESS: f32 = 0.3;
+const ISLAND_COLOR: [f32; 3] = [0.9, 0.85, 0.6];
+
+/// Direction a star or island sits in, in the sky dome's own frame. Same as the quads
+/// `mesh::stars` builds.
+pub fn star_direction(star: &StarDef) -> Vec3 {
+    star.quat * Vec3::Y
+}
+
+fn texel_direction(column: usize, row: usize) -> Vec3 {
+    let longitude = ((column as f32 + 0.5) / MAP_WIDTH as f32 - 0.5) * TAU;
+    let colatitude = (row as f32 + 0.5) / MAP_HEIGHT as f32 * PI;
+    Vec3::new(
+        colatitude.sin() * longitude.sin(),
+        colatitude.cos(),
+        colatitude.sin() * longitude.cos(),
+    )
+}
+
+/// Texel a direction falls in, the inverse of `texel_direction`.
+fn direction_texel(direction: Vec3) -> (f32, f32) {
+    let column = (direction.x.atan2(direction.z) / TAU + 0.5) * MAP_WIDTH as f32;
+    let row = direction.y.clamp(-1., 1.).acos() / PI * MAP_HEIGHT as f32;
+    (column, row)
+}
+
+/// The sky dome as an equirectangular map for the water to reflect: the night blue of
+/// the sky sphere with the stars and the islands painted on. Sampled with the same
+/// rotation as the dome.
+pub fn sky_map(stars: &[StarDef], islands: &[StarDef]) -> Image {
+    let mut texels: Vec<Vec3> = (0..MAP_WIDTH * MAP_HEIGHT)
+        .map(|i| {
+            let up = texel_direction(i % MAP_WIDTH, i / MAP_WIDTH).y;
+            let sky = Color::MIDNIGHT_BLUE * (0.1 + up * 0.2).max(0.);
+            Vec3::new(sky.r(), sky.g(), sky.b())
+        })
+        .collect();
+
+    // stars are smaller than a texel, spread thin over one so they keep their light
+    let texel_angle = PI / MAP_HEIGHT as f32;
+    let paint = |texels: &mut Vec<Vec3>, star: &StarDef, color: Vec3| {
+        let direction = star_direction(star);
+        let radius = star.size / STAR_DISTANCE / 2.;
+        let sigma = radius.max(texel_angle);
+        let strength = (radius / sigma).powi(2);
+        let (column, row) = direction_texel(direction);
+        let rows = (3. * sigma / texel_angle).ceil() as i32;
+        let columns = (rows as f32 / (row / MAP_HEIGHT as f32 * PI).sin().max(0.05))
+            .min(MAP_WIDTH as f32 / 2.) as i32;
+        for r in row as i32 - rows..=row as i32 + rows {
+            if !(0..MAP_HEIGHT as i32).contains(&r) {
+                continue;
+            }
+            for c in column as i32 - columns..=column as i32 + columns {
+                let c = c.rem_euclid(MAP_WIDTH as i32) as usize;
+                let angle = texel_direction(c, r as usize)
+                    .angle_between(direction)
+                    .abs();
+                let weight = strength * (-(angle / sigma).powi(2)).exp();
+                let texel = &mut texels[r as usize * MAP_WIDTH + c];
+                *texel = texel.lerp(color, weight.min(1.));
+            }
+        }
+    };
+    for star in stars {
+        paint(&mut texels, star, Vec3::splat(STAR_BRIGHTNESS));
+    }
+    for island in islands {
+        paint(&mut texels, island, Vec3::from(ISLAND_COLOR));
+    }
+
+    let data = texels
+        .iter()
+        .flat_map(|texel| {
+            let [r, g, b] = texel
+                .to_array()
+                .map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+            [r, g, b, 255]
+        })
+        .collect();
+    Image::new(
+        Extent3d {
+            width: MAP_WIDTH as u32,
+            height: MAP_HEIGHT as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_map_paints_stars_where_the_dome_has_them() {
+        let star = StarDef {
+            quat: Quat::from_rotation_z(-1.),
+            size: 0.02 * STAR_DISTANCE,
+        };
+        let direction = star_direction(&star);
+        let image = sky_map(&[star], &[]);
+        let brightness = |direction: Vec3| {
+            let (column, row) = direction_texel(direction);
+            image.data[(row as usize * MAP_WIDTH + column as usize) * 4] as f32
+        };
+        assert!(brightness(direction) > 3. * brightness(Quat::from_rotation_z(1.) * Vec3::Y));
+        let (column, row) = direction_texel(direction);
+        assert!(texel_direction(column as usize, row as usize).angle_between(direction) < 0.01);
+    }
+}
@@ -13,7 +13,7 @@ pub struct StarDef {
     pub size: f32,
 }
 
-pub fn bg_stars() -> Mesh {
+pub fn random_stars() -> Vec<StarDef> {
     let mut quat_rng = RandomRotation::new();
     let mut rng = rand::thread_rng();
     let dist = Uniform::from(0.001..0.005);
@@ -27,15 +27,18 @@ pub fn bg_stars() -> Mesh {
             });
         }
     }
-    stars(simple_stars)
+    simple_stars
 }
-pub fn island_stars(defs: Vec<StarDef>) -> Mesh {
+pub fn bg_stars(defs: &[StarDef]) -> Mesh {
+    stars(defs)
+}
+pub fn island_stars(defs: &[StarDef]) -> Mesh {
     stars(defs)
 }
 
 const NORMAL: [f32; 3] = [0.0, 1.0, 0.1];
 
-fn stars(defs: Vec<StarDef>) -> Mesh {
+fn stars(defs: &[StarDef]) -> Mesh {
     let mut vertices: Vec<([f32; 3], [f32; 3], [f32; 2])> = vec![];
     let mut tri_indices: Vec<u32> = vec![];
 
@@ -1,10 +1,13 @@
+use crate::water::reflection::UNREFLECTED_LAYER;
 use crate::DayTime;
 use crate::InGameState;
 use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
 use std::f32::consts::FRAC_PI_2;
 
 // use self::sphere_material::SkySphereMaterial;
 use self::star_material::SkyStarMaterial;
+mod map;
 mod mesh;
 // mod sphere_material;
 mod star_material;
@@ -18,6 +21,8 @@ pub struct SkyDomeLayerBg;
 pub struct SkyDome {
     pub rotation: Quat,
     pub locked_island: bool,
+    /// the dome painted on a texture for the water to reflect
+    pub map: Handle<Image>,
 }
 
 impl SkyDome {
@@ -25,6 +30,7 @@ impl SkyDome {
         SkyDome {
             rotation: Quat::IDENTITY,
             locked_island: false,
+            map: Handle::default(),
         }
     }
 }
@@ -63,6 +69,8 @@ pub fn spawn_sky(
     mut materials: ResMut<Assets<StandardMaterial>>,
     // mut render_graph: ResMut<RenderGraph>,
     asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut skydome: ResMut<SkyDome>,
 ) {
     // let texture_handle: Handle<Image> = asset_server.load("star.png");
     let sky_sphere_material_handle = sky_star_materials.add(SkyStarMaterial {
@@ -80,6 +88,7 @@ pub fn spawn_sky(
             ..Default::default()
         })
         .insert(Name::new("SkySphere"))
+        .insert(RenderLayers::layer(UNREFLECTED_LAYER))
         .insert(SkyDomeLayer)
         .insert(SkyDomeLayerBg);
 
@@ -88,15 +97,17 @@ pub fn spawn_sky(
         background: 0,
     });
 
+    let stars = mesh::random_stars();
     commands
         .spawn()
         .insert_bundle(MaterialMeshBundle {
-            mesh: meshes.add(mesh::bg_stars()),
+            mesh: meshes.add(mesh::bg_stars(&stars)),
             material: sky_material_handle,
             ..Default::default()
         })
         // .insert(sky_material_handle)
         .insert(Name::new("SkyStars"))
+        .insert(RenderLayers::layer(UNREFLECTED_LAYER))
         .insert(SkyDomeLayer)
         .insert(SkyDomeLayerBg);
 
@@ -137,13 +148,15 @@ pub fn spawn_sky(
         .collect();
     commands
         .spawn_bundle(PbrBundle {
-            mesh: meshes.add(mesh::island_stars(island_stars)),
+            mesh: meshes.add(mesh::island_stars(&island_stars)),
             material: sky_material_islands,
             ..Default::default()
         })
         .insert(Name::new("SkyIslands"))
+        .insert(RenderLayers::layer(UNREFLECTED_LAYER))
         .insert(SkyDomeLayer)
         .insert(SkyDomeLayerBg);
+    skydome.map = images.add(map::sky_map(&stars, &island_stars));
 
     for island in islands {
         let name = format!("SkyDomeIsland-{:?}", island.id);
@@ -198,7 +211,7 @@ pub fn skydome_system(
                         let value = ((distance_frac - 0.1).max(0.) * 10.).min(0.75);
                         // println!("approach value {} (dist. {})", value, distance_frac);
                         clear_color.0 = Color::rgb(value - 0.3, value - 0.2, value);
-                        weather.wave_intensity = 1. - value;
+                        weather.shelter = value;
 
                         const LOCK_DISTANCE: f32 = 0.2;
                         if distance_frac > LOCK_DISTANCE && !skydome.locked_island {
@@ -1,4 +1,10 @@
 use crate::boat;
+use crate::boat_definition::BoatDefinition;
+use crate::sail::PointOfSail;
+use crate::water::clock::WaveClock;
+use crate::water::rogue::RogueWaveEvent;
+use crate::water::weather::WeatherEvent;
+use crate::water::{Water, Weather};
 use crate::AppState;
 use bevy::{
     diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
@@ -17,13 +23,15 @@ pub fn add_systems(app: &mut bevy::prelude::App) -> &mut bevy::prelude::App {
     app.add_startup_system(spawn_ui);
     app.add_system(text_update_fps_system);
     app.add_system(text_update_hud_system);
+    app.add_system(text_update_weather_system);
+    app.add_system(text_update_rogue_system);
 
     app.add_system_set(SystemSet::on_update(AppState::Menu).with_system(ui_example));
 
     app
 }
 
-fn spawn_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
+fn spawn_ui(mut commands: Commands, asset_server: Res<AssetServer>, weather: Res<Weather>) {
     let font = asset_server.load("fonts/VCR_OSD_MONO_1.001.ttf");
     let font_size = 10.;
 
@@ -127,6 +135,78 @@ fn spawn_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                                     color: Color::RED,
                                 },
                             },
+                            TextSection {
+                                value: " Ground: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::RED,
+                                },
+                            },
+                            TextSection {
+                                value: " Sea: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: format!("{:?}", weather.state),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::GOLD,
+                                },
+                            },
+                            TextSection {
+                                value: " Boat: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::GOLD,
+                                },
+                            },
+                            TextSection {
+                                value: " Sail: ".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::WHITE,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::GOLD,
+                                },
+                            },
+                            TextSection {
+                                value: "".to_string(),
+                                style: TextStyle {
+                                    font: font.clone(),
+                                    font_size,
+                                    color: Color::RED,
+                                },
+                            },
                         ],
                         ..Default::default()
                     },
@@ -152,11 +232,75 @@ fn text_update_fps_system(
 fn text_update_hud_system(
     mut hud_query: Query<&mut Text, With<BoatHUDText>>,
     boat_query: Query<&boat::PlayerBoat>,
+    definitions: Res<Assets<BoatDefinition>>,
 ) {
     for mut text in hud_query.iter_mut() {
         let boat = boat_query.single();
         text.sections[1].value = format!("{:.2}", boat.throttle);
         text.sections[3].value = format!("{:.2}", boat.speed);
+        text.sections[5].value = format!("{:.2}", boat.speed_over_ground);
+        if let Some(definition) = definitions.get(&boat.definition) {
+            text.sections[9].value = if boat.moored {
+                format!("{} (B: change)", definition.name)
+            } else {
+                definition.name.clone()
+            };
+            text.sections[11].value = if definition.sail.is_some() {
+                format!(
+                    "{:?} sheet {:.0}%{}",
+                    PointOfSail::from_angle(boat.rig.wind_angle),
+                    boat.rig.sheet * 100.,
+                    if boat.rig.auto_trim { " auto" } else { "" }
+                )
+            } else {
+                "-".to_string()
+            };
+        }
+    }
+}
+
+fn text_update_weather_system(
+    mut events: EventReader<WeatherEvent>,
+    mut hud_query: Query<&mut Text, With<BoatHUDText>>,
+) {
+    for ev in events.iter() {
+        for mut text in hud_query.iter_mut() {
+            text.sections[7].value = match ev {
+                WeatherEvent::Turning { from, to } => format!("{:?} > {:?}", from, to),
+                WeatherEvent::Settled(state) => format!("{:?}", state),
+            };
+        }
+    }
+}
+
+/// Shows a rogue wave from its warning until it has passed, counting down the real
+/// seconds until it stands up.
+fn text_update_rogue_system(
+    clock: Res<WaveClock>,
+    mut events: EventReader<RogueWaveEvent>,
+    mut forecast: Local<Option<f32>>,
+    water_query: Query<&Water>,
+    mut hud_query: Query<&mut Text, With<BoatHUDText>>,
+) {
+    for ev in events.iter() {
+        *forecast = match ev {
+            RogueWaveEvent::Warning { height, .. } => Some(*height),
+            RogueWaveEvent::Passed => None,
+        };
+    }
+    // the wave clock runs at the weather's wave speed, the player waits in real time
+    let seconds = water_query.iter().find_map(|water| {
+        let rogue = water.rogue.as_ref()?;
+        Some(rogue.seconds_left(&clock) / (water.wave_speed * clock.scale).max(0.01))
+    });
+    for mut text in hud_query.iter_mut() {
+        text.sections[12].value = match (*forecast, seconds) {
+            (Some(height), Some(seconds)) if seconds > 0. => {
+                format!(" Rogue wave {:.0}m in {:.0}s", height, seconds)
+            }
+            (Some(_), _) => " Rogue wave!".to_string(),
+            (None, _) => "".to_string(),
+        };
     }
 }
 
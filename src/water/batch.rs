@@ -0,0 +1,459 @@
+use super::fft::FftOcean;
+use super::foam::{self, FOAM_HISTORY, FOAM_HISTORY_STEP};
+use super::rogue::Envelope;
+use super::shore::{ShoaledWave, Shore, ShorePoint};
+use super::{Water, WaveBackend, WaveClock, WaveData, WaveProperties};
+use bevy::prelude::*;
+use bevy::tasks::TaskPool;
+use std::f32::consts::PI;
+
+const SURFACE_MAX_ITERATIONS: usize = 16;
+/// Points evaluated together, the inner loops run over these so they can vectorize.
+const LANES: usize = 8;
+/// Points per task when spreading a batch over the task pool.
+const TASK_POINTS: usize = 64;
+
+/// Waves frozen at one moment, with everything that only depends on the wave and the
+/// time worked out once. Build one per frame with `Water::wave_batch` and query it for
+/// as many points as needed.
+pub enum WaveBatch<'a> {
+    Gerstner(Box<GerstnerConstants>),
+    Fft(&'a FftOcean),
+}
+
+/// Struct of arrays, one entry per wave with a non-zero steepness.
+#[derive(Default)]
+pub struct GerstnerConstants {
+    direction_x: Vec<f32>,
+    direction_z: Vec<f32>,
+    k: Vec<f32>,
+    amplitude: Vec<f32>,
+    /// phase minus the distance travelled, f = k * (d . p) + offset
+    offset: Vec<f32>,
+    /// angular frequency, how fast the offset falls with time
+    omega: Vec<f32>,
+    /// waves from this one on are those of the rogue wave, held to `envelope`
+    rogue: usize,
+    envelope: Option<Envelope>,
+    shore: Shore,
+}
+
+impl<'a> WaveBatch<'a> {
+    pub fn new(water: &'a Water, clock: &WaveClock) -> Self {
+        match &water.backend {
+            WaveBackend::Fft(ocean) => WaveBatch::Fft(ocean),
+            WaveBackend::Gerstner => {
+                let mut constants = GerstnerConstants {
+                    shore: water.shore.clone(),
+                    ..Default::default()
+                };
+                // the shader only sees these, so we must not sum more here either
+                for wave in water.sea_waves() {
+                    constants.push(wave, clock);
+                }
+                constants.rogue = constants.k.len();
+                if let Some(rogue) = &water.rogue {
+                    constants.envelope = Some(rogue.envelope(clock));
+                    for wave in rogue.waves.iter() {
+                        constants.push(wave, clock);
+                    }
+                }
+                WaveBatch::Gerstner(Box::new(constants))
+            }
+        }
+    }
+
+    /// Displaced surface point that started out at `point`.
+    pub fn wave_data_at_point(&self, point: Vec2) -> WaveData {
+        match self {
+            WaveBatch::Gerstner(constants) => {
+                let [wavedata] = constants.evaluate([point.x], [point.y]);
+                wavedata
+            }
+            WaveBatch::Fft(ocean) => ocean.wave_data_at_point(point),
+        }
+    }
+
+    pub fn wave_data_at_points(&self, points: &[Vec2]) -> Vec<WaveData> {
+        match self {
+            WaveBatch::Gerstner(constants) => {
+                let mut out = Vec::with_capacity(points.len());
+                for chunk in points.chunks(LANES) {
+                    let mut x = [0.; LANES];
+                    let mut z = [0.; LANES];
+                    for (i, point) in chunk.iter().enumerate() {
+                        x[i] = point.x;
+                        z[i] = point.y;
+                    }
+                    out.extend_from_slice(&constants.evaluate(x, z)[..chunk.len()]);
+                }
+                out
+            }
+            WaveBatch::Fft(ocean) => points
+                .iter()
+                .map(|point| ocean.wave_data_at_point(*point))
+                .collect(),
+        }
+    }
+
+    /// Surface exactly above `point`. The waves move the surface sideways, so this
+    /// searches for the undisplaced grid point that ends up within `tolerance` of `point`.
+    pub fn surface_at_point(&self, point: Vec2, tolerance: f32) -> WaveData {
+        self.grid_point_under(point, tolerance).1
+    }
+
+    /// Foam from 0 to 1 on the surface above `point`, see `foam`.
+    pub fn foam_at_point(&self, point: Vec2, tolerance: f32) -> f32 {
+        let (grid_point, _) = self.grid_point_under(point, tolerance);
+        match self {
+            WaveBatch::Gerstner(constants) => constants.foam(grid_point),
+            WaveBatch::Fft(ocean) => ocean.foam_at_point(grid_point),
+        }
+    }
+
+    fn grid_point_under(&self, point: Vec2, tolerance: f32) -> (Vec2, WaveData) {
+        let mut grid_point = point;
+        let mut wavedata = self.wave_data_at_point(grid_point);
+        for _ in 0..SURFACE_MAX_ITERATIONS {
+            match surface_step(point, &wavedata, tolerance) {
+                Some(step) => grid_point -= step,
+                None => break,
+            }
+            wavedata = self.wave_data_at_point(grid_point);
+        }
+        (grid_point, wavedata)
+    }
+
+    /// `surface_at_point` for many points, the ones still searching are evaluated together.
+    pub fn surface_at_points(&self, points: &[Vec2], tolerance: f32) -> Vec<WaveData> {
+        let mut grid_points = points.to_vec();
+        let mut wavedata = self.wave_data_at_points(&grid_points);
+        let mut pending: Vec<usize> = (0..points.len()).collect();
+        for _ in 0..SURFACE_MAX_ITERATIONS {
+            pending.retain(
+                |&i| match surface_step(points[i], &wavedata[i], tolerance) {
+                    Some(step) => {
+                        grid_points[i] -= step;
+                        true
+                    }
+                    None => false,
+                },
+            );
+            if pending.is_empty() {
+                break;
+            }
+            let pending_points: Vec<Vec2> = pending.iter().map(|&i| grid_points[i]).collect();
+            for (&i, data) in pending
+                .iter()
+                .zip(self.wave_data_at_points(&pending_points))
+            {
+                wavedata[i] = data;
+            }
+        }
+        wavedata
+    }
+
+    /// `surface_at_points` split over the tasks of `pool`, results keep the order of `points`.
+    pub fn par_surface_at_points(
+        &self,
+        points: &[Vec2],
+        tolerance: f32,
+        pool: &TaskPool,
+    ) -> Vec<WaveData> {
+        if points.len() <= TASK_POINTS {
+            return self.surface_at_points(points, tolerance);
+        }
+        pool.scope(|scope| {
+            for chunk in points.chunks(TASK_POINTS) {
+                scope.spawn(async move { self.surface_at_points(chunk, tolerance) });
+            }
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// Newton step towards the grid point under `point`, none once within `tolerance`.
+fn surface_step(point: Vec2, wavedata: &WaveData, tolerance: f32) -> Option<Vec2> {
+    let error = Vec2::new(wavedata.position.x, wavedata.position.z) - point;
+    if error.length() <= tolerance {
+        return None;
+    }
+    // tangent and binormal are the derivatives along grid x and z
+    let jacobian = Mat2::from_cols(
+        Vec2::new(wavedata.tangent.x, wavedata.tangent.z),
+        Vec2::new(wavedata.binormal.x, wavedata.binormal.z),
+    );
+    Some(if jacobian.determinant().abs() > f32::EPSILON {
+        jacobian.inverse() * error
+    } else {
+        error
+    })
+}
+
+impl GerstnerConstants {
+    fn push(&mut self, wave: &WaveProperties, clock: &WaveClock) {
+        if wave.steepness == 0. {
+            return;
+        }
+        let d = wave.direction.normalize();
+        let k = 2. * PI / wave.wavelength;
+        self.direction_x.push(d.x);
+        self.direction_z.push(d.y);
+        self.k.push(k);
+        self.amplitude.push(wave.steepness / k);
+        self.offset.push(wave.offset(clock));
+        self.omega.push(wave.omega());
+    }
+
+    /// How much of the rogue wave there is at `point`, from 0 to 1.
+    fn packet_at(&self, point: Vec2) -> f32 {
+        self.envelope.map_or(0., |envelope| envelope.at(point))
+    }
+
+    /// Wave `w` at `point`, with `packet` of the rogue wave there and bent by the shore
+    /// there, and its phase. Each wave is taken as a plane wave around the point, the
+    /// shore changes it too slowly to matter.
+    fn wave_at(
+        &self,
+        w: usize,
+        point: Vec2,
+        packet: f32,
+        shore: Option<&ShorePoint>,
+    ) -> (ShoaledWave, f32) {
+        let direction = Vec2::new(self.direction_x[w], self.direction_z[w]);
+        let f = self.k[w] * direction.dot(point) + self.offset[w];
+        let amplitude = if w < self.rogue {
+            self.amplitude[w]
+        } else {
+            self.amplitude[w] * packet
+        };
+        let open = ShoaledWave::open_sea(direction, self.k[w], amplitude);
+        match shore {
+            Some(shore) => {
+                let wave = shore.shoal(open);
+                (wave, f + wave.phase)
+            }
+            None => (open, f),
+        }
+    }
+
+    /// Sum of all waves at `N` points. Same as gerstner_wave in water.wgsl, keep both in sync.
+    fn evaluate<const N: usize>(&self, x: [f32; N], z: [f32; N]) -> [WaveData; N] {
+        let mut position = [[0.; N]; 3];
+        let mut tangent = [[0.; N]; 3];
+        let mut binormal = [[0.; N]; 3];
+        let mut add = |i: usize, dx: f32, dz: f32, k: f32, a: f32, f: f32| {
+            let q = k * a;
+            let (sin, cos) = f.sin_cos();
+
+            position[0][i] += dx * (a * cos);
+            position[1][i] += a * sin + a;
+            position[2][i] += dz * (a * cos);
+
+            tangent[0][i] += -dx * dx * (q * sin);
+            tangent[1][i] += dx * (q * cos);
+            tangent[2][i] += -dx * dz * (q * sin);
+
+            binormal[0][i] += -dx * dz * (q * sin);
+            binormal[1][i] += dz * (q * cos);
+            binormal[2][i] += -dz * dz * (q * sin);
+        };
+        // the same for every wave
+        let points: [Vec2; N] = std::array::from_fn(|i| Vec2::new(x[i], z[i]));
+        let shore: [Option<ShorePoint>; N] = std::array::from_fn(|i| self.shore.at(points[i]));
+        let packet: [f32; N] = std::array::from_fn(|i| self.packet_at(points[i]));
+
+        if shore.iter().all(Option::is_none) {
+            // the open sea, the same plain sum in every lane
+            let open = [1.; N];
+            for w in 0..self.k.len() {
+                let (dx, dz, k) = (self.direction_x[w], self.direction_z[w], self.k[w]);
+                let (amplitude, offset) = (self.amplitude[w], self.offset[w]);
+                let scale = if w < self.rogue { &open } else { &packet };
+                for i in 0..N {
+                    add(
+                        i,
+                        dx,
+                        dz,
+                        k,
+                        amplitude * scale[i],
+                        k * (dx * x[i] + dz * z[i]) + offset,
+                    );
+                }
+            }
+        } else {
+            for w in 0..self.k.len() {
+                for i in 0..N {
+                    let (wave, f) = self.wave_at(w, points[i], packet[i], shore[i].as_ref());
+                    add(
+                        i,
+                        wave.direction.x,
+                        wave.direction.y,
+                        wave.k,
+                        wave.amplitude,
+                        f,
+                    );
+                }
+            }
+        }
+
+        std::array::from_fn(|i| {
+            let tangent = Vec3::new(1. + tangent[0][i], tangent[1][i], tangent[2][i]);
+            let binormal = Vec3::new(binormal[0][i], binormal[1][i], 1. + binormal[2][i]);
+            WaveData {
+                position: Vec3::new(x[i] + position[0][i], position[1][i], z[i] + position[2][i]),
+                normal: binormal.cross(tangent).normalize(),
+                binormal,
+                tangent,
+            }
+        })
+    }
+
+    /// Foam at the undisplaced `point`, from how hard the crests passing it were pinched
+    /// lately, and the surf of the waves breaking on the shore. Same as gerstner_foam in
+    /// water.wgsl, keep both in sync.
+    fn foam(&self, point: Vec2) -> f32 {
+        let mut dx_dx = [0.; FOAM_HISTORY];
+        let mut dz_dz = [0.; FOAM_HISTORY];
+        let mut dx_dz = [0.; FOAM_HISTORY];
+        let (mut surf, mut amplitude) = (0., 0.);
+        let shore = self.shore.at(point);
+        let packet = self.packet_at(point);
+        for w in 0..self.k.len() {
+            let (wave, f) = self.wave_at(w, point, packet, shore.as_ref());
+            let (dx, dz) = (wave.direction.x, wave.direction.y);
+            let q = wave.k * wave.amplitude;
+            // white water on the front of each breaking crest, as much as the wave is of the sea
+            surf += wave.breaking * wave.amplitude * (0.5 + 0.5 * f.sin());
+            amplitude += wave.amplitude;
+            for h in 0..FOAM_HISTORY {
+                // the wave was this much further back, h steps ago
+                let sin = (f + self.omega[w] * h as f32 * FOAM_HISTORY_STEP).sin();
+                dx_dx[h] -= dx * dx * q * sin;
+                dz_dz[h] -= dz * dz * q * sin;
+                dx_dz[h] -= dx * dz * q * sin;
+            }
+        }
+        let whitecaps = foam::from_history(std::array::from_fn(|h| {
+            foam::whitecap((1. + dx_dx[h]) * (1. + dz_dz[h]) - dx_dz[h] * dx_dz[h])
+        }));
+        whitecaps.max(surf / amplitude.max(f32::EPSILON))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::water::rogue::RogueWave;
+    use crate::water::shore::{Bathymetry, Shoal};
+    use crate::water::spectrum::{SeaState, Spectrum};
+
+    #[test]
+    fn batched_surface_matches_single_points() {
+        let sea_state = SeaState {
+            spectrum: Spectrum::PiersonMoskowitz,
+            wind_speed: 12.,
+            components: 32,
+            seed: 7,
+            ..Default::default()
+        };
+        let mut water = Water {
+            waves: sea_state.waves(),
+            backend: WaveBackend::Gerstner,
+            wave_speed: 1.,
+            color: Color::SEA_GREEN,
+            shore: Shore::default(),
+            rogue: None,
+        };
+        water.shore.shoals.push(Shoal {
+            center: Vec2::new(60., 0.),
+            bathymetry: Bathymetry {
+                radius: 30.,
+                slope: 0.05,
+                depth: 25.,
+            },
+        });
+        let mut clock = WaveClock::default();
+        clock.step(3.7);
+        // neither a whole number of lanes nor of tasks
+        let points: Vec<Vec2> = (0..203)
+            .map(|i| Vec2::new(i as f32 * 0.7 - 20., (i as f32 * 0.37).sin() * 40.))
+            .collect();
+        let pool = TaskPool::new();
+
+        let batch = water.wave_batch(&clock);
+        let single: Vec<WaveData> = points
+            .iter()
+            .map(|point| batch.surface_at_point(*point, 0.001))
+            .collect();
+        for batched in [
+            batch.surface_at_points(&points, 0.001),
+            batch.par_surface_at_points(&points, 0.001, &pool),
+        ] {
+            assert_eq!(batched.len(), points.len());
+            for (i, (batched, single)) in batched.iter().zip(single.iter()).enumerate() {
+                assert!(
+                    (batched.position - single.position).length() < 1e-5,
+                    "{:?}: {:?} != {:?}",
+                    points[i],
+                    batched.position,
+                    single.position
+                );
+                assert!(
+                    batched.normal.dot(single.normal) > 0.99999,
+                    "{:?}",
+                    points[i]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn open_sea_lanes_match_shoaled_lanes() {
+        let mut clock = WaveClock::default();
+        let rogue = RogueWave::new(7, Vec2::ZERO, Vec2::X, 0.5, &clock);
+        clock.step(rogue.seconds_left(&clock));
+        let focus = rogue.focus;
+        let mut water = Water {
+            waves: SeaState::default().waves(),
+            backend: WaveBackend::Gerstner,
+            wave_speed: 1.,
+            color: Color::SEA_GREEN,
+            shore: Shore::default(),
+            rogue: Some(rogue),
+        };
+        water.shore.shoals.push(Shoal {
+            center: focus + Vec2::new(500., 0.),
+            bathymetry: Bathymetry {
+                radius: 30.,
+                slope: 0.05,
+                depth: 25.,
+            },
+        });
+        let batch = water.wave_batch(&clock);
+        let constants = match &batch {
+            WaveBatch::Gerstner(constants) => constants,
+            WaveBatch::Fft(_) => unreachable!(),
+        };
+
+        let x: [f32; LANES] = std::array::from_fn(|i| focus.x + i as f32 * 3. - 10.);
+        let z: [f32; LANES] = std::array::from_fn(|i| focus.y + (i as f32).sin() * 5.);
+        assert!(constants.packet_at(focus) > 0.5);
+        let open = constants.evaluate(x, z);
+        // one lane on the shoal takes the others the long way round
+        let mut shoaled_x = x;
+        shoaled_x[LANES - 1] = focus.x + 500.;
+        let shoaled = constants.evaluate(shoaled_x, z);
+        for i in 0..LANES - 1 {
+            assert!(
+                (open[i].position - shoaled[i].position).length() < 1e-5,
+                "{:?} != {:?}",
+                open[i].position,
+                shoaled[i].position
+            );
+            assert!(open[i].normal.dot(shoaled[i].normal) > 0.99999);
+        }
+    }
+}
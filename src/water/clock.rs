@@ -0,0 +1,109 @@
+use crate::AppState;
+use bevy::prelude::*;
+use std::f64::consts::TAU;
+
+/// The one source of ocean time, everything that moves with the waves reads it from here.
+/// Counted in f64 and only handed out wrapped, as phases or within a period, so the sea
+/// looks the same after hours as after seconds.
+#[derive(Debug, Clone)]
+pub struct WaveClock {
+    /// wave time since the start
+    seconds: f64,
+    /// real seconds played since the start, whatever the wave speed
+    game_seconds: f64,
+    /// wave time per real second, on top of the weather's wave speed
+    pub scale: f32,
+    /// stopped, with the game in the menu
+    pub paused: bool,
+}
+
+impl Default for WaveClock {
+    fn default() -> Self {
+        WaveClock {
+            seconds: 0.,
+            game_seconds: 0.,
+            scale: 1.,
+            paused: false,
+        }
+    }
+}
+
+impl WaveClock {
+    /// Moves on by `delta` real seconds, at `wave_speed` times the scale.
+    pub fn tick(&mut self, delta: f32, wave_speed: f32) {
+        if !self.paused {
+            self.step(delta * wave_speed * self.scale);
+            self.game_seconds += delta as f64;
+        }
+    }
+
+    /// Moves on by `seconds` of wave time, paused or not.
+    pub fn step(&mut self, seconds: f32) {
+        self.seconds += seconds as f64;
+    }
+
+    pub fn seconds(&self) -> f64 {
+        self.seconds
+    }
+
+    /// How far a wave of angular frequency `omega` has turned by now, from 0 to 2π.
+    pub fn phase(&self, omega: f32) -> f32 {
+        (omega as f64 * self.seconds).rem_euclid(TAU) as f32
+    }
+
+    /// How far something of angular frequency `omega` in game time has turned by now, from
+    /// 0 to 2π. For what keeps to the game's day instead of the waves, like the tide.
+    pub fn game_phase(&self, omega: f32) -> f32 {
+        (omega as f64 * self.game_seconds).rem_euclid(TAU) as f32
+    }
+
+    /// Wave time gone by since `last`, which moves on to now. For anything that runs on
+    /// from one update to the next, so it keeps to the waves however the clock goes.
+    pub fn since(&self, last: &mut f64) -> f32 {
+        let delta = (self.seconds - *last).max(0.) as f32;
+        *last = self.seconds;
+        delta
+    }
+
+    /// Time within a `period`, for anything that repeats with it.
+    pub fn wrapped(&self, period: f32) -> f32 {
+        self.seconds.rem_euclid(period as f64) as f32
+    }
+}
+
+pub fn wave_clock_system(
+    time: Res<Time>,
+    state: Res<State<AppState>>,
+    mut clock: ResMut<WaveClock>,
+    water_query: Query<&super::Water>,
+) {
+    clock.paused = *state.current() == AppState::Menu;
+    let wave_speed = water_query
+        .iter()
+        .next()
+        .map_or(1., |water| water.wave_speed);
+    clock.tick(time.delta_seconds(), wave_speed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_stay_exact_in_long_sessions() {
+        let omega = 1.7;
+        let mut short = WaveClock::default();
+        short.step(12.5);
+        // a week later, to the period
+        let mut long = WaveClock::default();
+        let period = TAU / omega as f64;
+        long.step(12.5);
+        long.seconds += (7. * 24. * 3600. / period).round() * period;
+        assert!((short.phase(omega) - long.phase(omega)).abs() < 1e-3);
+
+        long.paused = true;
+        let before = long.seconds();
+        long.tick(1., 1.);
+        assert_eq!(long.seconds(), before);
+    }
+}
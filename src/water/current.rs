@@ -0,0 +1,234 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::PI;
+
+/// Half the side in m of the square the gyres and streams are spread over.
+const EXTENT: f32 = 20_000.;
+const GYRES: usize = 6;
+const STREAMS: usize = 3;
+
+/// A rotating cell of water, turning from +x towards +z for a positive speed.
+#[derive(Debug, Clone)]
+pub struct Vortex {
+    pub center: Vec2,
+    /// m from the center where the water runs fastest
+    pub radius: f32,
+    /// m/s at `radius`
+    pub speed: f32,
+}
+
+/// A narrow river in the sea, meandering along a straight axis.
+#[derive(Debug, Clone)]
+pub struct Stream {
+    pub origin: Vec2,
+    /// the way it flows, normalized
+    pub direction: Vec2,
+    /// m from the axis where the flow has dropped to a third
+    pub width: f32,
+    /// m/s on the axis
+    pub speed: f32,
+    /// m the axis swings to either side, and the m it takes for one swing
+    pub meander: (f32, f32),
+}
+
+/// Sea water moving over the ground. Boats and swimmers drift with it on top of
+/// whatever they do through the water.
+#[derive(Debug, Clone, Default)]
+pub struct Current {
+    pub gyres: Vec<Vortex>,
+    pub streams: Vec<Stream>,
+    /// small vortices shed in the lee of islands, see `set_islands`
+    pub eddies: Vec<Vortex>,
+}
+
+impl Vortex {
+    fn at(&self, point: Vec2) -> Vec2 {
+        let offset = point - self.center;
+        let r2 = offset.length_squared() / (self.radius * self.radius);
+        // grows linearly from the center, peaks at the radius and fades outside
+        offset.perp() / self.radius * self.speed * (0.5 * (1. - r2)).exp()
+    }
+}
+
+impl Stream {
+    fn at(&self, point: Vec2) -> Vec2 {
+        let across = self.direction.perp();
+        let offset = point - self.origin;
+        let along = self.direction.dot(offset);
+        let (amplitude, wavelength) = self.meander;
+        let k = 2. * PI / wavelength;
+        let axis = amplitude * (k * along).sin();
+        let distance = across.dot(offset) - axis;
+        let flow = (self.direction + across * amplitude * k * (k * along).cos()).normalize();
+        flow * self.speed * (-(distance / self.width).powi(2)).exp()
+    }
+}
+
+impl Current {
+    /// Gyres and streams at random, the same for a given seed.
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let point = |rng: &mut StdRng| {
+            Vec2::new(
+                rng.gen_range(-EXTENT, EXTENT),
+                rng.gen_range(-EXTENT, EXTENT),
+            )
+        };
+        let gyres = (0..GYRES)
+            .map(|_| Vortex {
+                center: point(&mut rng),
+                radius: rng.gen_range(2_000., 5_000.),
+                speed: rng.gen_range(0.3, 0.8) * if rng.gen() { 1. } else { -1. },
+            })
+            .collect();
+        let streams = (0..STREAMS)
+            .map(|_| {
+                let angle = rng.gen_range(-PI, PI);
+                Stream {
+                    origin: point(&mut rng),
+                    direction: Vec2::new(angle.cos(), angle.sin()),
+                    width: rng.gen_range(100., 300.),
+                    speed: rng.gen_range(1., 2.),
+                    meander: (rng.gen_range(100., 600.), rng.gen_range(2_000., 6_000.)),
+                }
+            })
+            .collect();
+        Current {
+            gyres,
+            streams,
+            eddies: vec![],
+        }
+    }
+
+    /// Velocity of the water at `point` in world xz, in m/s.
+    pub fn at(&self, point: Vec2) -> Vec2 {
+        self.eddies
+            .iter()
+            .fold(self.open_sea_at(point), |sum, eddy| sum + eddy.at(point))
+    }
+
+    /// The current without the eddies islands cause.
+    fn open_sea_at(&self, point: Vec2) -> Vec2 {
+        let gyres = self.gyres.iter().map(|gyre| gyre.at(point));
+        let streams = self.streams.iter().map(|stream| stream.at(point));
+        gyres
+            .chain(streams)
+            .fold(Vec2::ZERO, |sum, flow| sum + flow)
+    }
+
+    /// Replaces the eddies with a counter-rotating pair behind each island, on the side
+    /// the open sea current flows away to.
+    pub fn set_islands(&mut self, islands: &[Vec2]) {
+        self.eddies.clear();
+        for island in islands {
+            let flow = self.open_sea_at(*island);
+            let speed = flow.length();
+            if speed < 0.05 {
+                continue;
+            }
+            let downstream = flow / speed;
+            for side in [-1., 1.] {
+                self.eddies.push(Vortex {
+                    center: *island + downstream * 250. + downstream.perp() * side * 120.,
+                    radius: 100.,
+                    // the flow past each side of the island rolls up into its eddy
+                    speed: speed * -side,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream() -> Stream {
+        Stream {
+            origin: Vec2::new(100., -50.),
+            direction: Vec2::new(0.6, 0.8),
+            width: 200.,
+            speed: 1.5,
+            meander: (300., 4000.),
+        }
+    }
+
+    fn streams_only(streams: Vec<Stream>) -> Current {
+        Current {
+            gyres: vec![],
+            streams,
+            eddies: vec![],
+        }
+    }
+
+    #[test]
+    fn stream_runs_along_its_axis_and_fades_to_the_sides() {
+        let stream = stream();
+        let current = streams_only(vec![stream.clone()]);
+        let across = stream.direction.perp();
+        let k = 2. * PI / stream.meander.1;
+        for i in 0..40 {
+            let along = i as f32 * 250. - 5000.;
+            let axis = stream.origin
+                + stream.direction * along
+                + across * stream.meander.0 * (k * along).sin();
+            let flow = current.at(axis);
+            assert!((flow.length() - stream.speed).abs() < 1e-4, "{:?}", flow);
+            // downstream, though swinging with the meander
+            assert!(
+                flow.dot(stream.direction) > 0.5 * stream.speed,
+                "{:?}",
+                flow
+            );
+
+            for side in [-1., 1.] {
+                let edge = current.at(axis + across * side * stream.width);
+                let ratio = edge.length() / stream.speed;
+                assert!((ratio - (-1f32).exp()).abs() < 1e-3, "{}", ratio);
+                assert!(
+                    current
+                        .at(axis + across * side * 4. * stream.width)
+                        .length()
+                        < 1e-3
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn islands_shed_an_eddy_pair_downstream() {
+        // far wider than the eddies, so the open sea flows the same all around them
+        let stream = Stream {
+            origin: Vec2::ZERO,
+            direction: Vec2::X,
+            width: 1e6,
+            speed: 1.,
+            meander: (0., 1000.),
+        };
+        let mut current = streams_only(vec![stream]);
+        current.set_islands(&[Vec2::ZERO]);
+        assert_eq!(current.eddies.len(), 2);
+
+        let eddies = |point: Vec2| current.at(point) - current.open_sea_at(point);
+        for eddy in current.eddies.iter() {
+            assert!(eddy.center.x > 0., "{:?}", eddy);
+            // running as fast as the current around its ring
+            for i in 0..8 {
+                let angle = i as f32 * PI / 4.;
+                let ring = eddy.center + Vec2::new(angle.cos(), angle.sin()) * eddy.radius;
+                let own = eddy.at(ring).length();
+                assert!((own - 1.).abs() < 1e-4, "{}", own);
+            }
+            assert!(eddy.at(eddy.center).length() < 1e-6);
+        }
+        // between the pair the water runs back towards the island
+        let between = eddies(Vec2::new(250., 0.));
+        assert!(between.x < -1., "{:?}", between);
+        assert!(eddies(Vec2::new(-1000., 0.)).length() < 1e-3);
+
+        // nothing to shed in slack water
+        let mut slack = streams_only(vec![]);
+        slack.set_islands(&[Vec2::ZERO]);
+        assert!(slack.eddies.is_empty());
+    }
+}
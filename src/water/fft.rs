@@ -0,0 +1,452 @@
+use super::foam;
+use super::spectrum::{SeaState, GRAVITY};
+use super::{WaveClock, WaveData};
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Sub};
+
+/// Tessendorf ocean: a wave spectrum evolved in frequency space and inverse transformed
+/// into a tiling field of heights, horizontal displacements and slopes.
+#[derive(Debug)]
+pub struct FftOcean {
+    /// texels per tile side, a power of two
+    pub size: usize,
+    /// tile side in m
+    pub length: f32,
+    /// scale of the horizontal displacement, 0 gives round sine-like crests
+    pub choppiness: f32,
+    h0: Vec<Complex>,
+    h0_minus_conj: Vec<Complex>,
+    omega: Vec<f32>,
+    /// (dx, height, dz) per texel, row major with z as row
+    pub displacement: Vec<Vec3>,
+    /// (dh/dx, dh/dz) per texel
+    pub slope: Vec<Vec2>,
+    /// whitecaps built up and fading per texel, from 0 to 1
+    pub foam: Vec<f32>,
+    /// wave time of the last `update`, the foam builds up from there
+    foam_time: Option<f64>,
+}
+
+impl FftOcean {
+    pub fn new(sea_state: &SeaState, size: usize, length: f32) -> Self {
+        assert!(size.is_power_of_two());
+        let mut ocean = FftOcean {
+            size,
+            length,
+            choppiness: 1.,
+            h0: vec![],
+            h0_minus_conj: vec![],
+            omega: vec![],
+            displacement: vec![Vec3::ZERO; size * size],
+            slope: vec![Vec2::ZERO; size * size],
+            foam: vec![0.; size * size],
+            foam_time: None,
+        };
+        ocean.set_spectrum(sea_state);
+        ocean
+    }
+
+    /// Swaps in a new sea state without the waves jumping. A calmer or rougher sea of the
+    /// same seed keeps its random phases, only the amplitudes change.
+    pub fn set_sea_state(&mut self, sea_state: &SeaState) {
+        self.set_spectrum(sea_state);
+    }
+
+    fn set_spectrum(&mut self, sea_state: &SeaState) {
+        let size = self.size;
+        let mut rng = StdRng::seed_from_u64(sea_state.seed);
+        let wind_angle = sea_state.wind_direction.y.atan2(sea_state.wind_direction.x);
+        let spreading = sea_state.directional_spreading();
+        let dk = 2. * PI / self.length;
+
+        let mut h0 = vec![Complex::ZERO; size * size];
+        let mut omega = vec![0.; size * size];
+        for row in 0..size {
+            for column in 0..size {
+                let k = wave_vector(column, row, size, self.length);
+                let k_length = k.length();
+                // the nyquist modes have no conjugate partner, leave them out
+                let nyquist = row == size / 2 || column == size / 2;
+                if k_length == 0. || nyquist {
+                    continue;
+                }
+                let i = row * size + column;
+                omega[i] = (GRAVITY * k_length).sqrt();
+                // drawn even without wind, so every mode keeps its random numbers
+                let random = gaussian(&mut rng);
+                if sea_state.wind_speed <= 0. {
+                    continue;
+                }
+
+                // S(ω) D(θ) over to wavenumber space: Ψ(k) = S(ω) D(θ) dω/dk / k
+                let theta = wrap_angle(k.y.atan2(k.x) - wind_angle);
+                let density =
+                    sea_state.density(omega[i]) * spreading(theta) * (GRAVITY / (2. * omega[i]))
+                        / k_length;
+                // a wave and its conjugate share the variance, gaussian() has two unit parts
+                let amplitude = (density * dk * dk).sqrt() / 2.;
+                h0[i] = random * amplitude;
+            }
+        }
+        self.h0_minus_conj = (0..size * size)
+            .map(|i| {
+                let (row, column) = (i / size, i % size);
+                h0[((size - row) % size) * size + (size - column) % size].conj()
+            })
+            .collect();
+        self.h0 = h0;
+        self.omega = omega;
+    }
+
+    /// Evolves the spectrum to the time of `clock` and refreshes the tile.
+    pub fn update(&mut self, clock: &WaveClock) {
+        let size = self.size;
+        let mut height_dx = vec![Complex::ZERO; size * size];
+        let mut dz_slope_x = vec![Complex::ZERO; size * size];
+        let mut slope_z_dx_dz = vec![Complex::ZERO; size * size];
+        let mut dx_dx_dz_dz = vec![Complex::ZERO; size * size];
+
+        for i in 0..size * size {
+            let k = wave_vector(i % size, i / size, size, self.length);
+            let k_length = k.length();
+            if k_length == 0. {
+                continue;
+            }
+            let phase = Complex::from_angle(clock.phase(self.omega[i]));
+            // h0(k) travels towards k, its partner towards -k
+            let h = self.h0[i] * phase.conj() + self.h0_minus_conj[i] * phase;
+            let k_unit = k / k_length;
+
+            // the fields are real, so two of them share one transform as real + i * imaginary
+            let dx = h.times_i() * k_unit.x;
+            let dz = h.times_i() * k_unit.y;
+            let sx = h.times_i() * k.x;
+            let sz = h.times_i() * k.y;
+            // derivatives of the horizontal displacement, for the Jacobian
+            let dx_dx = h * -(k.x * k_unit.x);
+            let dz_dz = h * -(k.y * k_unit.y);
+            let dx_dz = h * -(k.x * k_unit.y);
+            height_dx[i] = h + dx.times_i();
+            dz_slope_x[i] = dz + sx.times_i();
+            slope_z_dx_dz[i] = sz + dx_dz.times_i();
+            dx_dx_dz_dz[i] = dx_dx + dz_dz.times_i();
+        }
+
+        inverse_fft_2d(&mut height_dx, size);
+        inverse_fft_2d(&mut dz_slope_x, size);
+        inverse_fft_2d(&mut slope_z_dx_dz, size);
+        inverse_fft_2d(&mut dx_dx_dz_dz, size);
+
+        let delta = match self.foam_time {
+            Some(foam_time) => ((clock.seconds() - foam_time) as f32).clamp(0., foam::FOAM_DECAY),
+            None => 0.,
+        };
+        self.foam_time = Some(clock.seconds());
+        let c = self.choppiness;
+
+        for i in 0..size * size {
+            self.displacement[i] = Vec3::new(
+                height_dx[i].im * self.choppiness,
+                height_dx[i].re,
+                dz_slope_x[i].re * self.choppiness,
+            );
+            self.slope[i] = Vec2::new(dz_slope_x[i].im, slope_z_dx_dz[i].re);
+            let jacobian = (1. + c * dx_dx_dz_dz[i].re) * (1. + c * dx_dx_dz_dz[i].im)
+                - (c * slope_z_dx_dz[i].im).powi(2);
+            self.foam[i] = foam::accumulate(self.foam[i], foam::whitecap(jacobian), delta);
+        }
+    }
+
+    /// Bilinear sample of the last `update`, the tile repeats every `length` m.
+    pub fn wave_data_at_point(&self, point: Vec2) -> WaveData {
+        let mut displacement = Vec3::ZERO;
+        let mut slope = Vec2::ZERO;
+        for (i, weight) in self.corners(point) {
+            displacement += self.displacement[i] * weight;
+            slope += self.slope[i] * weight;
+        }
+
+        let tangent = Vec3::new(1., slope.x, 0.);
+        let binormal = Vec3::new(0., slope.y, 1.);
+        WaveData {
+            position: Vec3::new(point.x, 0., point.y) + displacement,
+            normal: binormal.cross(tangent).normalize(),
+            binormal,
+            tangent,
+        }
+    }
+
+    /// Bilinear sample of the foam of the last `update` at the undisplaced `point`.
+    pub fn foam_at_point(&self, point: Vec2) -> f32 {
+        self.corners(point)
+            .iter()
+            .map(|(i, weight)| self.foam[*i] * weight)
+            .sum()
+    }
+
+    /// Texels around `point` with their bilinear weights.
+    fn corners(&self, point: Vec2) -> [(usize, f32); 4] {
+        let texel = point / self.length * self.size as f32;
+        let base = texel.floor();
+        let t = texel - base;
+        let size = self.size as i32;
+        let index = |x: f32, z: f32| {
+            let column = (x as i32).rem_euclid(size) as usize;
+            let row = (z as i32).rem_euclid(size) as usize;
+            row * self.size + column
+        };
+        [
+            (index(base.x, base.y), (1. - t.x) * (1. - t.y)),
+            (index(base.x + 1., base.y), t.x * (1. - t.y)),
+            (index(base.x, base.y + 1.), (1. - t.x) * t.y),
+            (index(base.x + 1., base.y + 1.), t.x * t.y),
+        ]
+    }
+
+    /// Tile packed for the water shader: displacement in xyz, slopes in the second image
+    /// with the foam in z.
+    pub fn texture_data(&self) -> (Vec<[f32; 4]>, Vec<[f32; 4]>) {
+        (
+            self.displacement
+                .iter()
+                .map(|d| [d.x, d.y, d.z, 0.])
+                .collect(),
+            self.slope
+                .iter()
+                .zip(self.foam.iter())
+                .map(|(s, foam)| [s.x, s.y, *foam, 0.])
+                .collect(),
+        )
+    }
+}
+
+/// Wave vector of a texel in FFT order, the upper half of the indices are negative.
+fn wave_vector(column: usize, row: usize, size: usize, length: f32) -> Vec2 {
+    let signed = |n: usize| {
+        if n < size / 2 {
+            n as f32
+        } else {
+            n as f32 - size as f32
+        }
+    };
+    Vec2::new(signed(column), signed(row)) * 2. * PI / length
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(2. * PI) - PI
+}
+
+/// Complex number with independent standard normal parts, Box-Muller.
+fn gaussian(rng: &mut StdRng) -> Complex {
+    let u1: f32 = rng.gen_range(f32::EPSILON, 1.);
+    let u2: f32 = rng.gen_range(0., 2. * PI);
+    let r = (-2. * u1.ln()).sqrt();
+    Complex::new(r * u2.cos(), r * u2.sin())
+}
+
+fn inverse_fft_2d(data: &mut [Complex], size: usize) {
+    let mut column = vec![Complex::ZERO; size];
+    for row in data.chunks_mut(size) {
+        inverse_fft(row);
+    }
+    for x in 0..size {
+        for z in 0..size {
+            column[z] = data[z * size + x];
+        }
+        inverse_fft(&mut column);
+        for z in 0..size {
+            data[z * size + x] = column[z];
+        }
+    }
+}
+
+/// In place radix-2 transform with a positive exponent and no normalization,
+/// so the output is the plain sum of the waves.
+fn inverse_fft(data: &mut [Complex]) {
+    let n = data.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            data.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let step = Complex::from_angle(2. * PI / len as f32);
+        for start in (0..n).step_by(len) {
+            let mut w = Complex::ONE;
+            for k in 0..len / 2 {
+                let even = data[start + k];
+                let odd = data[start + k + len / 2] * w;
+                data[start + k] = even + odd;
+                data[start + k + len / 2] = even - odd;
+                w = w * step;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    const ZERO: Complex = Complex { re: 0., im: 0. };
+    const ONE: Complex = Complex { re: 1., im: 0. };
+
+    fn new(re: f32, im: f32) -> Self {
+        Complex { re, im }
+    }
+    fn from_angle(angle: f32) -> Self {
+        Complex::new(angle.cos(), angle.sin())
+    }
+    fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+    fn times_i(self) -> Self {
+        Complex::new(-self.im, self.re)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Mul<f32> for Complex {
+    type Output = Complex;
+    fn mul(self, scale: f32) -> Complex {
+        Complex::new(self.re * scale, self.im * scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::water::spectrum::Spectrum;
+
+    const SIZE: usize = 32;
+    const LENGTH: f32 = 64.;
+
+    fn ocean() -> FftOcean {
+        let sea_state = SeaState {
+            spectrum: Spectrum::PiersonMoskowitz,
+            wind_speed: 12.,
+            seed: 7,
+            ..Default::default()
+        };
+        FftOcean::new(&sea_state, SIZE, LENGTH)
+    }
+
+    /// Heights of the spectrum at `time`, before the transform.
+    fn height_spectrum(ocean: &FftOcean, time: f32) -> Vec<Complex> {
+        let mut clock = WaveClock::default();
+        clock.step(time);
+        (0..SIZE * SIZE)
+            .map(|i| {
+                let phase = Complex::from_angle(clock.phase(ocean.omega[i]));
+                ocean.h0[i] * phase.conj() + ocean.h0_minus_conj[i] * phase
+            })
+            .collect()
+    }
+
+    fn assert_close(a: Complex, b: Complex, tolerance: f32) {
+        assert!(
+            (a.re - b.re).abs() < tolerance && (a.im - b.im).abs() < tolerance,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn inverse_fft_matches_naive_sum() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let input: Vec<Complex> = (0..SIZE * SIZE).map(|_| gaussian(&mut rng)).collect();
+        let mut output = input.clone();
+        inverse_fft_2d(&mut output, SIZE);
+
+        for row in 0..SIZE {
+            for column in 0..SIZE {
+                let mut sum = Complex::ZERO;
+                for (i, value) in input.iter().enumerate() {
+                    let turns = ((i / SIZE) * row + (i % SIZE) * column) % SIZE;
+                    sum = sum + *value * Complex::from_angle(2. * PI * turns as f32 / SIZE as f32);
+                }
+                assert_close(output[row * SIZE + column], sum, 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn height_spectrum_is_hermitian() {
+        let ocean = ocean();
+        let spectrum = height_spectrum(&ocean, 2.5);
+        for row in 0..SIZE {
+            for column in 0..SIZE {
+                let partner = ((SIZE - row) % SIZE) * SIZE + (SIZE - column) % SIZE;
+                assert_close(
+                    spectrum[partner],
+                    spectrum[row * SIZE + column].conj(),
+                    1e-6,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tile_is_real_and_repeats_every_length() {
+        let ocean = ocean();
+        let spectrum = height_spectrum(&ocean, 2.5);
+        let mut tile = spectrum.clone();
+        inverse_fft_2d(&mut tile, SIZE);
+        let highest = tile.iter().map(|h| h.re.abs()).fold(0., f32::max);
+        assert!(highest > 0.1, "flat sea: {}", highest);
+        for height in &tile {
+            assert!(height.im.abs() < highest * 1e-4, "{:?}", height);
+        }
+
+        // summed as waves at the texel's spot a tile or two away, it lands on the texel
+        for (row, column) in [(0, 0), (3, 17), (31, 8)] {
+            let point = Vec2::new(column as f32, row as f32) * LENGTH / SIZE as f32
+                + Vec2::new(LENGTH, -2. * LENGTH);
+            let mut height = Complex::ZERO;
+            for (i, h) in spectrum.iter().enumerate() {
+                let k = wave_vector(i % SIZE, i / SIZE, SIZE, LENGTH);
+                height = height + *h * Complex::from_angle(k.dot(point));
+            }
+            assert_close(height, tile[row * SIZE + column], highest * 1e-3);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+/// Jacobian of the horizontal displacement below which a crest starts to break. 1 is
+/// undisturbed water, 0 is a crest pinched so hard that it folds over itself.
+pub const BREAKING_JACOBIAN: f32 = 0.5;
+/// How far below `BREAKING_JACOBIAN` a whitecap goes from nothing to full.
+const WHITECAP_RANGE: f32 = 0.4;
+/// Seconds, of wave time, for foam to fade to a third.
+pub const FOAM_DECAY: f32 = 1.5;
+/// Foam gained per second of wave time on a full whitecap, 1 is fully white.
+pub const FOAM_BUILDUP: f32 = 1.;
+/// Gerstner waves have no memory, so their foam sums up the whitecaps at this many
+/// moments in the past, `FOAM_HISTORY_STEP` apart. Same as in water.wgsl.
+pub const FOAM_HISTORY: usize = 4;
+pub const FOAM_HISTORY_STEP: f32 = 0.5;
+
+/// Whitecap strength from 0 to 1 at a point with this displacement Jacobian.
+pub fn whitecap(jacobian: f32) -> f32 {
+    ((BREAKING_JACOBIAN - jacobian) / WHITECAP_RANGE).clamp(0., 1.)
+}
+
+/// Foam `delta` seconds later, built up by `whitecap` and fading meanwhile.
+pub fn accumulate(foam: f32, whitecap: f32, delta: f32) -> f32 {
+    (foam * (-delta / FOAM_DECAY).exp() + whitecap * FOAM_BUILDUP * delta).min(1.)
+}
+
+/// Foam from the whitecaps at the moments of `FOAM_HISTORY`, newest first. The same as
+/// `accumulate` would have built up, sampled coarsely.
+pub fn from_history(whitecaps: [f32; FOAM_HISTORY]) -> f32 {
+    whitecaps
+        .iter()
+        .enumerate()
+        .map(|(i, whitecap)| {
+            let age = i as f32 * FOAM_HISTORY_STEP;
+            whitecap * FOAM_BUILDUP * FOAM_HISTORY_STEP * (-age / FOAM_DECAY).exp()
+        })
+        .sum::<f32>()
+        .min(1.)
+}
@@ -0,0 +1,163 @@
+use super::WaveData;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// Points on the waterline of a floating object where the water height is sampled,
+/// in the object's own xz with -z towards the bow.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hull {
+    pub points: Vec<Vec2>,
+    /// m² the hull covers seen from above, shared evenly by the points for buoyancy
+    pub area: f32,
+    /// m from keel to deck, centred on the object's origin. A fully submerged hull
+    /// displaces no more than `area * height`.
+    pub height: f32,
+}
+
+/// How a hull sits in the water: the height at its origin and the up vector of the
+/// plane that fits the water under it best.
+#[derive(Debug, Clone, Copy)]
+pub struct HullPose {
+    pub height: f32,
+    pub normal: Vec3,
+}
+
+impl Default for Hull {
+    /// A single point, the object tilts with the water right under its origin.
+    fn default() -> Self {
+        Hull {
+            points: vec![Vec2::ZERO],
+            area: 1.,
+            height: 1.,
+        }
+    }
+}
+
+impl Hull {
+    /// Bow, stern, port, starboard, the four corners and the middle of a `width` (x) by
+    /// `height` (y) by `length` (z) box.
+    pub fn rectangle(width: f32, height: f32, length: f32) -> Self {
+        let (x, z) = (width / 2., length / 2.);
+        Hull {
+            points: vec![
+                Vec2::ZERO,
+                Vec2::new(0., -z),
+                Vec2::new(0., z),
+                Vec2::new(-x, 0.),
+                Vec2::new(x, 0.),
+                Vec2::new(-x, -z),
+                Vec2::new(x, -z),
+                Vec2::new(-x, z),
+                Vec2::new(x, z),
+            ],
+            area: width * length,
+            height,
+        }
+    }
+
+    /// m across (x) and along (y) the box around the points.
+    pub fn size(&self) -> Vec2 {
+        let (min, max) = self.points.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), point| (min.min(*point), max.max(*point)),
+        );
+        (max - min).max(Vec2::ZERO)
+    }
+
+    /// Sample points in world xz for a hull at `translation` turned `world_rotation`
+    /// radians around y.
+    pub fn world_points(&self, translation: Vec3, world_rotation: f32) -> Vec<Vec2> {
+        let rotation = Quat::from_rotation_y(world_rotation);
+        self.points
+            .iter()
+            .map(|point| {
+                let offset = rotation * Vec3::new(point.x, 0., point.y);
+                Vec2::new(translation.x + offset.x, translation.z + offset.z)
+            })
+            .collect()
+    }
+
+    /// Sample points in world space for a hull at `translation` tilted by `rotation`, half
+    /// way between keel and deck.
+    pub fn body_points(&self, translation: Vec3, rotation: Quat) -> Vec<Vec3> {
+        self.points
+            .iter()
+            .map(|point| translation + rotation * Vec3::new(point.x, 0., point.y))
+            .collect()
+    }
+
+    /// Least squares plane y = height + slope . offset through the water surfaces under
+    /// the hull's points, `surfaces` in the order of `world_points`. A hull longer than
+    /// the waves rides on their average instead of following every crest.
+    pub fn pose(&self, translation: Vec3, surfaces: &[WaveData]) -> HullPose {
+        let mean_normal = surfaces
+            .iter()
+            .fold(Vec3::ZERO, |sum, surface| sum + surface.normal)
+            .normalize_or_zero();
+        // normal equations, [1, dx, dz] . [height, slope x, slope z] = y for every point
+        let mut ata = Mat3::ZERO;
+        let mut aty = Vec3::ZERO;
+        for surface in surfaces {
+            let row = Vec3::new(
+                1.,
+                surface.position.x - translation.x,
+                surface.position.z - translation.z,
+            );
+            ata += Mat3::from_cols(row * row.x, row * row.y, row * row.z);
+            aty += row * surface.position.y;
+        }
+        if ata.determinant().abs() <= f32::EPSILON {
+            // one point, or all in a line: no plane, so use the waves' own normals
+            let height = surfaces
+                .iter()
+                .map(|surface| surface.position.y)
+                .sum::<f32>()
+                / surfaces.len().max(1) as f32;
+            return HullPose {
+                height,
+                normal: if mean_normal == Vec3::ZERO {
+                    Vec3::Y
+                } else {
+                    mean_normal
+                },
+            };
+        }
+        let plane = ata.inverse() * aty;
+        HullPose {
+            height: plane.x,
+            normal: Vec3::new(-plane.y, 1., -plane.z).normalize(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pose_fits_a_tilted_plane() {
+        let hull = Hull::rectangle(3.8, 1., 4.4);
+        let translation = Vec3::new(5., 0., -3.);
+        // y = 2 + 0.3 x - 0.2 z around the hull
+        let (height, slope) = (2., Vec2::new(0.3, -0.2));
+        let surfaces: Vec<WaveData> = hull
+            .world_points(translation, 0.6)
+            .iter()
+            .map(|point| {
+                let offset = *point - Vec2::new(translation.x, translation.z);
+                WaveData {
+                    position: Vec3::new(point.x, height + slope.dot(offset), point.y),
+                    // the fit must come from the heights, not the normals
+                    normal: Vec3::Y,
+                    binormal: Vec3::Z,
+                    tangent: Vec3::X,
+                }
+            })
+            .collect();
+
+        let pose = hull.pose(translation, &surfaces);
+        assert!((pose.height - height).abs() < 1e-5, "{}", pose.height);
+        let normal = Vec3::new(-slope.x, 1., -slope.y).normalize();
+        assert!((pose.normal - normal).length() < 1e-5, "{:?}", pose.normal);
+    }
+}
@@ -1,11 +1,177 @@
 use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
 use bevy::render::mesh::MeshVertexBufferLayout;
-use bevy::render::render_resource::{AsBindGroup, ShaderRef, RenderPipelineDescriptor, SpecializedMeshPipelineError};
+use bevy::render::render_resource::{AsBindGroup, Extent3d, ShaderRef, ShaderType, RenderPipelineDescriptor, SpecializedMeshPipelineError, TextureDimension, TextureFormat};
 use bevy::{
     prelude::*,
     reflect::TypeUuid,
 };
 
+use super::fft::FftOcean;
+use super::ripples::Ripples;
+use super::shore::{Shore, MAX_SHOALS};
+use super::wake::{Wake, MAX_WAKE_SOURCES};
+use super::{Water, WaveClock, MAX_WAVES};
+
+#[derive(ShaderType, Clone, Copy, Debug)]
+pub struct WaveUniform {
+    pub properties: Vec4,
+    pub phase: f32,
+}
+
+/// Wave list as the shader sees it, a fixed size array and how many entries are used.
+#[derive(ShaderType, Clone, Debug)]
+pub struct WaterWaves {
+    pub count: u32,
+    pub waves: [WaveUniform; MAX_WAVES],
+}
+
+impl WaterWaves {
+    /// Phases are the waves' offsets at the time of `clock`, the shader needs no time.
+    /// The sea's waves come first, then those of the rogue wave.
+    pub fn new(water: &Water, clock: &WaveClock) -> Self {
+        let rogue = water.rogue.iter().flat_map(|rogue| rogue.waves.iter());
+        let waves: Vec<_> = water.sea_waves().iter().chain(rogue).collect();
+        let mut uniform = WaterWaves {
+            count: waves.len().min(MAX_WAVES) as u32,
+            waves: [WaveUniform {
+                properties: Vec4::ZERO,
+                phase: 0.,
+            }; MAX_WAVES],
+        };
+        for (slot, wave) in uniform.waves.iter_mut().zip(waves) {
+            *slot = WaveUniform {
+                properties: wave.to_vec4(),
+                phase: wave.offset(clock),
+            };
+        }
+        uniform
+    }
+}
+
+#[derive(ShaderType, Clone, Copy, Debug)]
+pub struct WakeRingUniform {
+    /// xy: center, z: radius, w: amplitude
+    pub ring: Vec4,
+    /// x: wave number, y: packet, z: churn, w: width
+    pub shape: Vec4,
+}
+
+/// Rings of `Wake` as the shader sees them.
+#[derive(ShaderType, Clone, Debug)]
+pub struct WakeUniform {
+    pub count: u32,
+    pub rings: [WakeRingUniform; MAX_WAKE_SOURCES],
+}
+
+impl WakeUniform {
+    pub fn new(wake: &Wake) -> Self {
+        let mut uniform = WakeUniform {
+            count: wake.sources.len().min(MAX_WAKE_SOURCES) as u32,
+            rings: [WakeRingUniform {
+                ring: Vec4::ZERO,
+                shape: Vec4::ZERO,
+            }; MAX_WAKE_SOURCES],
+        };
+        for (slot, source) in uniform.rings.iter_mut().zip(wake.sources.iter()) {
+            let ring = source.ring();
+            *slot = WakeRingUniform {
+                ring: Vec4::new(ring.center.x, ring.center.y, ring.radius, ring.amplitude),
+                shape: Vec4::new(ring.k, ring.packet, ring.churn, ring.width),
+            };
+        }
+        uniform
+    }
+}
+
+#[derive(ShaderType, Clone, Copy, Debug, Default)]
+pub struct ShoalUniform {
+    /// xy: center, z: radius, w: slope
+    pub shape: Vec4,
+    pub depth: f32,
+}
+
+/// Shoals of `Shore` as the shader sees them.
+#[derive(ShaderType, Clone, Debug, Default)]
+pub struct ShoreUniform {
+    pub count: u32,
+    pub tide: f32,
+    pub shoals: [ShoalUniform; MAX_SHOALS],
+}
+
+impl ShoreUniform {
+    pub fn new(shore: &Shore) -> Self {
+        let mut uniform = ShoreUniform {
+            count: shore.shoals.len().min(MAX_SHOALS) as u32,
+            tide: shore.tide,
+            ..Default::default()
+        };
+        for (slot, shoal) in uniform.shoals.iter_mut().zip(shore.shoals.iter()) {
+            let bathymetry = &shoal.bathymetry;
+            *slot = ShoalUniform {
+                shape: Vec4::new(
+                    shoal.center.x,
+                    shoal.center.y,
+                    bathymetry.radius,
+                    bathymetry.slope,
+                ),
+                depth: bathymetry.depth,
+            };
+        }
+        uniform
+    }
+}
+
+/// The packet the waves of `WaterWaves` from `first` on are held to, see `Envelope`.
+#[derive(ShaderType, Clone, Debug)]
+pub struct RogueUniform {
+    pub first: u32,
+    /// xy: center, zw: direction
+    pub shape: Vec4,
+    /// x: length, y: width, z: strength
+    pub size: Vec4,
+}
+
+impl RogueUniform {
+    pub fn new(water: &Water, clock: &WaveClock) -> Self {
+        match &water.rogue {
+            Some(rogue) => {
+                let envelope = rogue.envelope(clock);
+                RogueUniform {
+                    first: water.sea_waves().len() as u32,
+                    shape: Vec4::new(
+                        envelope.center.x,
+                        envelope.center.y,
+                        envelope.direction.x,
+                        envelope.direction.y,
+                    ),
+                    size: Vec4::new(envelope.length, envelope.width, envelope.strength, 0.),
+                }
+            }
+            None => RogueUniform {
+                first: MAX_WAVES as u32,
+                shape: Vec4::ZERO,
+                size: Vec4::ONE,
+            },
+        }
+    }
+}
+
+/// Where the ripple texture lies in the world, a size of 0 has no ripples.
+#[derive(ShaderType, Clone, Debug, Default)]
+pub struct RippleUniform {
+    pub corner: Vec2,
+    pub cell: f32,
+    pub size: u32,
+}
+
+/// Tells the shader to read the FFT tile textures instead of summing `waves`.
+#[derive(ShaderType, Clone, Debug, Default)]
+pub struct FftUniform {
+    pub enabled: u32,
+    pub size: u32,
+    pub length: f32,
+}
+
 #[derive(AsBindGroup, TypeUuid, Clone)]
 #[uuid = "463e4b8a-d555-4fc2-ba9f-4c880063ba92"]
 pub struct WaterMaterial {
@@ -16,20 +182,105 @@ pub struct WaterMaterial {
     #[uniform(2)]
     pub camera: Vec3,
     #[uniform(3)]
-    pub wave1: Vec4,
+    pub waves: WaterWaves,
     #[uniform(4)]
-    pub wave2: Vec4,
-    #[uniform(5)]
-    pub wave3: Vec4,
+    pub fft: FftUniform,
+    #[texture(5, filterable = false)]
+    pub fft_displacement: Option<Handle<Image>>,
+    #[texture(6, filterable = false)]
+    pub fft_slope: Option<Handle<Image>>,
+    #[uniform(7)]
+    pub wake: WakeUniform,
+    #[uniform(8)]
+    pub ripple: RippleUniform,
+    #[texture(9, filterable = false)]
+    pub ripple_height: Option<Handle<Image>>,
+    /// turns world directions into the frame of `sky_map`, a quaternion
+    #[uniform(10)]
+    pub sky_rotation: Vec4,
+    #[texture(11)]
+    #[sampler(12)]
+    pub sky_map: Option<Handle<Image>>,
+    /// what the reflection camera sees, upside down
+    #[texture(13)]
+    #[sampler(14)]
+    pub reflection: Option<Handle<Image>>,
+    #[uniform(15)]
+    pub shore: ShoreUniform,
+    /// rocks of the reefs, seen through shallow water
+    #[texture(16)]
+    #[sampler(17)]
+    pub rock: Option<Handle<Image>>,
+    #[uniform(18)]
+    pub rogue: RogueUniform,
+}
+
+impl WaterMaterial {
+    /// Uploads the current FFT tile, creating the images the first time.
+    pub fn set_fft_tile(&mut self, ocean: &FftOcean, images: &mut Assets<Image>) {
+        let (displacement, slope) = ocean.texture_data();
+        self.fft = FftUniform {
+            enabled: 1,
+            size: ocean.size as u32,
+            length: ocean.length,
+        };
+        upload(
+            &mut self.fft_displacement,
+            ocean.size,
+            &displacement,
+            images,
+        );
+        upload(&mut self.fft_slope, ocean.size, &slope, images);
+    }
+
+    /// Uploads the ripple grid, creating the image the first time.
+    pub fn set_ripples(&mut self, ripples: &Ripples, images: &mut Assets<Image>) {
+        self.ripple = RippleUniform {
+            corner: ripples.corner(),
+            cell: Ripples::CELL,
+            size: Ripples::SIZE as u32,
+        };
+        upload(
+            &mut self.ripple_height,
+            Ripples::SIZE,
+            &ripples.texture_data(),
+            images,
+        );
+    }
+}
+
+/// Writes a square of `size` texels into the image behind `handle`.
+fn upload(
+    handle: &mut Option<Handle<Image>>,
+    size: usize,
+    texels: &[[f32; 4]],
+    images: &mut Assets<Image>,
+) {
+    let data = bytemuck::cast_slice(texels).to_vec();
+    match handle.as_ref().and_then(|handle| images.get_mut(handle)) {
+        Some(image) if image.data.len() == data.len() => image.data = data,
+        _ => {
+            *handle = Some(images.add(Image::new(
+                Extent3d {
+                    width: size as u32,
+                    height: size as u32,
+                    depth_or_array_layers: 1,
+                },
+                TextureDimension::D2,
+                data,
+                TextureFormat::Rgba32Float,
+            )));
+        }
+    }
 }
 
 impl Material for WaterMaterial {
-    // fn fragment_shader() -> ShaderRef {
-        // "shaders/water.frag".into()
-    // }
-    // fn vertex_shader() -> ShaderRef {
-        // "shaders/water.vert".into()
-    // }
+    fn fragment_shader() -> ShaderRef {
+        "shaders/water.wgsl".into()
+    }
+    fn vertex_shader() -> ShaderRef {
+        "shaders/water.wgsl".into()
+    }
     // Bevy assumes by default that vertex shaders use the "vertex" entry point
     // and fragment shaders use the "fragment" entry point (for WGSL shaders).
     // GLSL uses "main" as the entry point, so we must override the defaults here
@@ -41,6 +292,8 @@ impl Material for WaterMaterial {
     ) -> Result<(), SpecializedMeshPipelineError> {
         // descriptor.vertex.entry_point = "main".into();
         // descriptor.fragment.as_mut().unwrap().entry_point = "main".into();
+        // seen from below when the camera goes under
+        descriptor.primitive.cull_mode = None;
         Ok(())
     }
 }
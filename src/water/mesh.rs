@@ -0,0 +1,240 @@
+use bevy::{
+    prelude::*,
+    render::mesh::{Indices, VertexAttributeValues},
+};
+use wgpu_types::PrimitiveTopology;
+
+/// m from the center where the last ring stretches out to, past the camera's far plane.
+const HORIZON: f32 = 5000.;
+
+/// Resolution of the water mesh, pick with the `WaterQuality` resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WaterQuality {
+    Low,
+    #[default]
+    Medium,
+    High,
+}
+
+struct ClipmapSettings {
+    /// m between vertices of the innermost level, doubles with every level
+    cell: f32,
+    /// cells along a side of a level, a multiple of 4 above 32
+    cells: u32,
+    levels: u32,
+}
+
+impl WaterQuality {
+    fn settings(self) -> ClipmapSettings {
+        match self {
+            WaterQuality::Low => ClipmapSettings {
+                cell: 1.,
+                cells: 48,
+                levels: 5,
+            },
+            WaterQuality::Medium => ClipmapSettings {
+                cell: 0.5,
+                cells: 64,
+                levels: 7,
+            },
+            WaterQuality::High => ClipmapSettings {
+                cell: 0.25,
+                cells: 128,
+                levels: 8,
+            },
+        }
+    }
+}
+
+/// Nested square rings of vertices, each level twice as coarse and twice as wide as the
+/// one inside it, then one last ring out to the horizon.
+///
+/// The rings are not placed here: water.wgsl snaps every level on its own to its own
+/// grid around the water transform, and morphs the outer cells of a level into the
+/// coarser one, so moving the transform never moves a vertex over the waves. For that
+/// the shader needs to know, per vertex, in uv:
+/// - x: the cell size of the level
+/// - y: half the side of the next level's hole, 0 for the last level
+pub fn clipmap(quality: WaterQuality) -> Mesh {
+    let settings = quality.settings();
+    // two more cells on each side, so a level still fills the next one's hole when the
+    // two are snapped apart by a coarse cell
+    let half = settings.cells as i32 / 2 + 2;
+
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    let mut cell = settings.cell;
+    for level in 0..settings.levels {
+        let hole = if level == 0 {
+            0
+        } else {
+            settings.cells as i32 / 4
+        };
+        let next_hole = if level + 1 < settings.levels {
+            settings.cells as f32 / 2. * cell
+        } else {
+            0.
+        };
+
+        let side = (2 * half + 1) as u32;
+        let first = positions.len() as u32;
+        for z in -half..=half {
+            for x in -half..=half {
+                positions.push([x as f32 * cell, 0., z as f32 * cell]);
+                uvs.push([cell, next_hole]);
+            }
+        }
+        let index = |x: i32, z: i32| first + (z + half) as u32 * side + (x + half) as u32;
+        for z in -half..half {
+            for x in -half..half {
+                let in_hole = x >= -hole && x < hole && z >= -hole && z < hole;
+                if in_hole {
+                    continue;
+                }
+                quad(
+                    &mut indices,
+                    [
+                        index(x, z),
+                        index(x + 1, z),
+                        index(x, z + 1),
+                        index(x + 1, z + 1),
+                    ],
+                );
+            }
+        }
+        if level + 1 == settings.levels {
+            horizon_ring(&mut positions, &mut uvs, &mut indices, half, cell);
+        }
+        cell *= 2.;
+    }
+
+    let normals = vec![[0., 1., 0.]; positions.len()];
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        VertexAttributeValues::from(positions),
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::from(normals));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::from(uvs));
+    mesh
+}
+
+/// Flat square of `cells` by `cells` quads of side `cell`, centered on the origin.
+pub fn grid(cells: u32, cell: f32) -> Mesh {
+    let half = cells as i32 / 2;
+    let side = (2 * half + 1) as u32;
+    let mut positions: Vec<[f32; 3]> = vec![];
+    let mut uvs: Vec<[f32; 2]> = vec![];
+    let mut indices: Vec<u32> = vec![];
+    for z in -half..=half {
+        for x in -half..=half {
+            positions.push([x as f32 * cell, 0., z as f32 * cell]);
+            uvs.push([
+                (x + half) as f32 / side as f32,
+                (z + half) as f32 / side as f32,
+            ]);
+        }
+    }
+    let index = |x: i32, z: i32| (z + half) as u32 * side + (x + half) as u32;
+    for z in -half..half {
+        for x in -half..half {
+            quad(
+                &mut indices,
+                [
+                    index(x, z),
+                    index(x + 1, z),
+                    index(x, z + 1),
+                    index(x + 1, z + 1),
+                ],
+            );
+        }
+    }
+
+    let normals = vec![[0., 1., 0.]; positions.len()];
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+    mesh.set_indices(Some(Indices::U32(indices)));
+    mesh.insert_attribute(
+        Mesh::ATTRIBUTE_POSITION,
+        VertexAttributeValues::from(positions),
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, VertexAttributeValues::from(normals));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, VertexAttributeValues::from(uvs));
+    mesh
+}
+
+/// Two triangles facing up, always split along the same diagonal. A level morphed into
+/// the next one then collapses into exactly the coarser triangles.
+fn quad(indices: &mut Vec<u32>, [a, b, c, d]: [u32; 4]) {
+    indices.extend_from_slice(&[a, c, b, b, c, d]);
+}
+
+/// Quads from the edge of the last level, with its vertices, straight out to `HORIZON`.
+fn horizon_ring(
+    positions: &mut Vec<[f32; 3]>,
+    uvs: &mut Vec<[f32; 2]>,
+    indices: &mut Vec<u32>,
+    half: i32,
+    cell: f32,
+) {
+    // walk the edge counter-clockwise seen from above, starting in a corner
+    let mut edge: Vec<(i32, i32)> = vec![];
+    for i in -half..half {
+        edge.push((i, -half));
+    }
+    for i in -half..half {
+        edge.push((half, i));
+    }
+    for i in (-half + 1..=half).rev() {
+        edge.push((i, half));
+    }
+    for i in (-half + 1..=half).rev() {
+        edge.push((-half, i));
+    }
+
+    let first = positions.len() as u32;
+    let scale = HORIZON / (half as f32 * cell);
+    for (x, z) in edge.iter() {
+        let inner = Vec3::new(*x as f32 * cell, 0., *z as f32 * cell);
+        let outer = inner * scale;
+        positions.push(inner.into());
+        positions.push(outer.into());
+        uvs.push([cell, 0.]);
+        uvs.push([cell, 0.]);
+    }
+    let count = edge.len() as u32;
+    for i in 0..count {
+        let j = (i + 1) % count;
+        let (inner, outer) = (first + 2 * i, first + 2 * i + 1);
+        let (next_inner, next_outer) = (first + 2 * j, first + 2 * j + 1);
+        indices.extend_from_slice(&[inner, next_inner, outer, outer, next_inner, next_outer]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clipmap_faces_up_and_reaches_the_horizon() {
+        let mesh = clipmap(WaterQuality::Low);
+        let positions = match mesh.attribute(Mesh::ATTRIBUTE_POSITION) {
+            Some(VertexAttributeValues::Float32x3(positions)) => positions,
+            _ => panic!("no positions"),
+        };
+        let indices = match mesh.indices() {
+            Some(Indices::U32(indices)) => indices,
+            _ => panic!("no indices"),
+        };
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| Vec3::from(positions[triangle[i] as usize]));
+            assert!((b - a).cross(c - a).y > 0., "{} {} {}", a, b, c);
+        }
+        let farthest = positions
+            .iter()
+            .map(|p| p[0].abs().max(p[2].abs()))
+            .fold(0., f32::max);
+        assert_eq!(farthest, HORIZON);
+    }
+}
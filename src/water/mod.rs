@@ -1,39 +1,137 @@
 use crate::boat::PlayerBoat;
+use crate::sky::SkyDome;
 use crate::AppState;
 use bevy::render::render_asset::RenderAssetPlugin;
 
+use bevy::ecs::system::SystemParam;
+use bevy::pbr::NotShadowCaster;
 use bevy::prelude::*;
+use bevy::render::view::RenderLayers;
 // use bevy_inspector_egui::Inspectable;
-use std::f32::consts::PI;
-use std::ops::AddAssign;
+use bevy::tasks::ComputeTaskPool;
+use std::marker::PhantomData;
+pub mod batch;
+pub mod clock;
+pub mod current;
+pub mod fft;
+pub mod foam;
+pub mod hull;
 mod material;
-use material::WaterMaterial;
+pub mod mesh;
+pub mod reflection;
+pub mod ripples;
+pub mod rogue;
+pub mod seabed;
+pub mod shore;
+pub mod spectrum;
+pub mod tide;
+pub mod underwater;
+pub mod wake;
+pub mod weather;
+pub mod wind;
+use batch::WaveBatch;
+pub use clock::WaveClock;
+pub use current::Current;
+use fft::FftOcean;
+use hull::Hull;
+use material::{
+    FftUniform, RippleUniform, RogueUniform, ShoreUniform, WakeUniform, WaterMaterial, WaterWaves,
+};
+pub use mesh::WaterQuality;
+use reflection::UNREFLECTED_LAYER;
+pub use ripples::Ripples;
+use ripples::SplashEvent;
+use rogue::{RogueSea, RogueWave};
+use seabed::SeabedMaterial;
+use shore::Shore;
+use spectrum::SeaState;
+pub use tide::Tide;
+use underwater::{GodRayMaterial, Underwater};
+pub use wake::Wake;
+pub use weather::Weather;
+use weather::WeatherEvent;
+pub use wind::Wind;
 
-pub struct Weather {
-    pub wave_intensity: f32,
+/// Upper bound of wave components, the water shader's uniform array has this size.
+pub const MAX_WAVES: usize = 64;
+
+/// What moves the water's surface along with the waves: the time they are at, and the
+/// current, wakes and ripples on top of them.
+#[derive(SystemParam)]
+pub struct WaterSurface<'w, 's> {
+    pub clock: Res<'w, WaveClock>,
+    pub current: Res<'w, Current>,
+    pub wake: Res<'w, Wake>,
+    pub ripples: Res<'w, Ripples>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+#[derive(Debug)]
+pub enum WaveBackend {
+    /// `Water::waves` summed up at every point
+    Gerstner,
+    /// FFT tile evolved once per frame by `fft_system`, `Water::waves` is not used. The
+    /// tile knows nothing of the shore, so it is for the open sea only: with shoals about
+    /// the water stays with, or goes back to, the Gerstner waves.
+    Fft(Box<FftOcean>),
 }
 
 #[derive(Component, Debug)]
 pub struct Water {
-    pub waves: [WaveProperties; 3],
+    pub waves: Vec<WaveProperties>,
+    pub backend: WaveBackend,
     pub wave_speed: f32,
     pub color: Color,
+    /// shallows around the islands, the Gerstner waves break on them
+    pub shore: Shore,
+    /// a freak wave on its way through the Gerstner waves
+    pub rogue: Option<RogueWave>,
 }
+/// Horizontal error in m that floating objects accept from `surface_at_point`.
+pub const SURFACE_TOLERANCE: f32 = 0.01;
+
 impl Water {
+    /// Precomputed waves at the time of `clock`, for querying many points.
+    pub fn wave_batch(self: &Self, clock: &WaveClock) -> WaveBatch<'_> {
+        WaveBatch::new(self, clock)
+    }
+    /// The sea's waves that fit in the shader next to the rogue wave's, if there is one.
+    pub fn sea_waves(&self) -> &[WaveProperties] {
+        let room = MAX_WAVES - self.rogue.as_ref().map_or(0, |rogue| rogue.waves.len());
+        &self.waves[..self.waves.len().min(room)]
+    }
     #[allow(dead_code)]
-    pub fn height_at_point(self: &Self, point: Vec2, time: f32) -> f32 {
-        let input_point = Vec3::new(point.x, 0., point.y);
-
-        let first_pass = wave_sequence(input_point, time, &self.waves);
-        first_pass.position.y
+    pub fn height_at_point(self: &Self, point: Vec2, clock: &WaveClock) -> f32 {
+        self.surface_at_point(point, clock, SURFACE_TOLERANCE)
+            .position
+            .y
     }
-    pub fn wave_data_at_point(self: &Self, point: Vec2, time: f32) -> WaveData {
-        let input_point = Vec3::new(point.x, 0., point.y);
-
-        wave_sequence(input_point, time, &self.waves)
+    /// See `WaveBatch::surface_at_point`.
+    pub fn surface_at_point(
+        self: &Self,
+        point: Vec2,
+        clock: &WaveClock,
+        tolerance: f32,
+    ) -> WaveData {
+        self.wave_batch(clock).surface_at_point(point, tolerance)
+    }
+    /// Whitecap foam from 0 to 1 on the surface above `point`, the same the shader draws.
+    /// Breaking crests are where it is high and rising.
+    #[allow(dead_code)]
+    pub fn foam_at_point(&self, point: Vec2, clock: &WaveClock) -> f32 {
+        self.wave_batch(clock)
+            .foam_at_point(point, SURFACE_TOLERANCE)
+    }
+    #[allow(dead_code)]
+    /// Displaced surface point that started out at `point`, this is what the shader renders
+    /// at a vertex. With the FFT backend `clock` is whatever the tile was last updated to.
+    pub fn wave_data_at_point(self: &Self, point: Vec2, clock: &WaveClock) -> WaveData {
+        self.wave_batch(clock).wave_data_at_point(point)
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub struct WaveData {
     pub position: Vec3,
     pub normal: Vec3,
@@ -41,11 +139,26 @@ pub struct WaveData {
     pub tangent: Vec3,
 }
 
-#[derive(Debug)]
+impl WaveData {
+    /// This surface with `height` more water on top, sloping by (dh/dx, dh/dz).
+    pub fn lifted(&self, height: f32, slope: Vec2) -> WaveData {
+        let tangent = self.tangent + Vec3::new(0., slope.x, 0.);
+        let binormal = self.binormal + Vec3::new(0., slope.y, 0.);
+        WaveData {
+            position: self.position + Vec3::Y * height,
+            normal: binormal.cross(tangent).normalize(),
+            binormal,
+            tangent,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct WaveProperties {
     pub wavelength: f32,
     pub steepness: f32,
     pub direction: Vec2,
+    pub phase: f32,
 }
 impl WaveProperties {
     pub fn to_vec4(self: &Self) -> Vec4 {
@@ -56,16 +169,117 @@ impl WaveProperties {
             self.steepness,
         )
     }
+    /// Angular frequency of a deep water wave this long.
+    pub fn omega(&self) -> f32 {
+        let k = 2. * std::f32::consts::PI / self.wavelength;
+        k * (9.8 / k).sqrt()
+    }
+    /// Phase at the origin at the time of `clock`, the wave is at k * (d . p) + offset.
+    /// Wrapped to a turn, so it is as exact after hours as at the start.
+    pub fn offset(&self, clock: &WaveClock) -> f32 {
+        (self.phase - clock.phase(self.omega())).rem_euclid(2. * std::f32::consts::PI)
+    }
 }
 
+/// kg/m³, sea water
+pub const WATER_DENSITY: f32 = 1025.;
+/// m/s a swimmer must fall at to splash when it hits the water.
+const SPLASH_SPEED: f32 = 2.;
+/// Longer frames are integrated as if they were this long, so a hitch can not fling
+/// swimmers out of the water.
+const MAX_FLOAT_STEP: f32 = 1. / 30.;
+/// rad the wind veers before the waves turn with it, each turn makes them anew.
+const WAVE_VEER_STEP: f32 = 0.05;
+/// m/s the wind speed drifts while the weather turns before the waves are made anew.
+const WAVE_WIND_SPEED_STEP: f32 = 0.25;
+
 #[derive(Component)]
 pub struct Swimmer {
     pub world_rotation: f32, // y angle in radians
+    pub hull: Hull,
+    /// kg, floats at a draft of mass / (WATER_DENSITY * hull area)
+    pub mass: f32,
+    /// m/s, up is positive
+    pub velocity: f32,
+    /// rad/s in world space, only pitch and roll, yaw stays `world_rotation`
+    pub angular_velocity: Vec3,
+    /// fraction of the velocity lost per second while fully submerged
+    pub linear_damping: f32,
+    pub angular_damping: f32,
 }
 impl Default for Swimmer {
     #[inline]
     fn default() -> Self {
-        Swimmer { world_rotation: 0. }
+        Swimmer {
+            world_rotation: 0.,
+            hull: Hull::rectangle(1., 1., 1.),
+            mass: 500.,
+            velocity: 0.,
+            angular_velocity: Vec3::ZERO,
+            linear_damping: 3.,
+            angular_damping: 4.,
+        }
+    }
+}
+
+impl Swimmer {
+    /// Moves the swimmer by gravity and the buoyancy of its hull over `delta` seconds.
+    /// `points` are the hull's `body_points` and `surfaces` the water surface above each,
+    /// relative to `water_level`.
+    pub fn float(
+        &mut self,
+        transform: &mut Transform,
+        points: &[Vec3],
+        surfaces: &[WaveData],
+        water_level: f32,
+        delta: f32,
+    ) {
+        let delta = delta.min(MAX_FLOAT_STEP);
+        let point_area = self.hull.area / points.len() as f32;
+        let mut force = -self.mass * spectrum::GRAVITY;
+        let mut torque = Vec3::ZERO;
+        let mut submerged = 0.;
+        for (point, surface) in points.iter().zip(surfaces) {
+            // each point carries a column of the hull, keel to deck
+            let depth = (surface.position.y + water_level - point.y + self.hull.height / 2.)
+                .clamp(0., self.hull.height);
+            let buoyancy = WATER_DENSITY * spectrum::GRAVITY * point_area * depth;
+            force += buoyancy;
+            torque += (*point - transform.translation).cross(Vec3::Y * buoyancy);
+            submerged += depth / self.hull.height / points.len() as f32;
+        }
+
+        // semi-implicit euler, water damps the motion and air hardly does
+        self.velocity += force / self.mass * delta;
+        self.velocity /= 1. + self.linear_damping * submerged * delta;
+        transform.translation.y += self.velocity * delta;
+
+        let yaw = Quat::from_rotation_y(self.world_rotation);
+        let tilt = if self.hull.points.len() < 3 {
+            // nothing to tip over on, lie flat on the water instead
+            surface_quat(self.hull.pose(transform.translation, surfaces).normal)
+        } else {
+            let body_torque = transform.rotation.inverse() * torque;
+            self.angular_velocity += transform.rotation * (body_torque / self.inertia()) * delta;
+            self.angular_velocity.y = 0.;
+            self.angular_velocity /= 1. + self.angular_damping * submerged * delta;
+            let up = Quat::from_scaled_axis(self.angular_velocity * delta)
+                * transform.rotation
+                * Vec3::Y;
+            // only the swing, so rounding can not creep into the yaw
+            Quat::from_rotation_arc(Vec3::Y, up.normalize())
+        };
+        transform.rotation = (tilt * yaw).normalize();
+    }
+
+    /// Moments of inertia around the body axes, the mass spread evenly over the hull
+    /// points and the hull height.
+    fn inertia(&self) -> Vec3 {
+        let count = self.hull.points.len().max(1) as f32;
+        let x2 = self.hull.points.iter().map(|p| p.x * p.x).sum::<f32>() / count;
+        let z2 = self.hull.points.iter().map(|p| p.y * p.y).sum::<f32>() / count;
+        let y2 = self.hull.height * self.hull.height / 12.;
+        self.mass * Vec3::new(z2 + y2, x2 + z2 + y2, x2 + y2)
     }
 }
 
@@ -75,163 +289,142 @@ pub struct WaterCamera;
 
 pub fn add_systems(app: &mut bevy::prelude::App) -> &mut bevy::prelude::App {
     // app.add_asset::<WaterMaterial>()
-    app.insert_resource(Weather {
-        wave_intensity: 1.0,
-    })
-    .add_plugin(MaterialPlugin::<WaterMaterial>::default())
-    .add_startup_system(setup)
-    .add_system_set(
-        SystemSet::on_update(AppState::InGame)
-            .with_system(update_system.label("water").after("physics"))
-            .with_system(wave_probe_system.label("water").after("physics")),
-    )
+    app.insert_resource(Weather::default())
+        .insert_resource(WaterQuality::default())
+        .insert_resource(Wind::default())
+        .insert_resource(Current::new(0))
+        .insert_resource(Wake::default())
+        .insert_resource(Ripples::default())
+        .insert_resource(Underwater::default())
+        .insert_resource(WaveClock::default())
+        .insert_resource(Tide::default())
+        .insert_resource(RogueSea::default())
+        .add_plugin(MaterialPlugin::<WaterMaterial>::default())
+        .add_plugin(MaterialPlugin::<SeabedMaterial>::default())
+        .add_plugin(MaterialPlugin::<GodRayMaterial>::default())
+        .add_startup_system(setup)
+        .add_startup_system(underwater::setup)
+        .add_startup_system(seabed::setup)
+        // in every state, the clock pauses itself in the menu
+        .add_system(
+            clock::wave_clock_system
+                .label("clock")
+                .before("waves")
+                .before("physics"),
+        )
+        .add_system_set(
+            SystemSet::on_update(AppState::InGame)
+                .with_system(
+                    weather_system
+                        .label("weather")
+                        .after("clock")
+                        .before("waves"),
+                )
+                .with_system(sea_state_system.after("weather").before("waves"))
+                .with_system(fft_system.label("waves").before("physics"))
+                .with_system(current_islands_system.before("physics"))
+                .with_system(shore_islands_system.before("physics"))
+                .with_system(tide::tide_system.after("clock").before("physics"))
+                .with_system(rogue::rogue_system.after("weather").before("physics"))
+                .with_system(wake_system.label("water").after("physics"))
+                .with_system(ripple_system.label("water").after("physics"))
+                .with_system(update_system.label("water").after("physics"))
+                .with_system(water_quality_system)
+                .with_system(wave_probe_system.label("water").after("physics"))
+                .with_system(reflection::reflection_system.after("camera"))
+                .with_system(reflection::resize_system)
+                .with_system(seabed::seabed_system.after("physics").after("camera"))
+                .with_system(
+                    underwater::underwater_system
+                        .label("water")
+                        .label("underwater")
+                        .after("physics")
+                        .after("camera"),
+                )
+                .with_system(underwater::fog_system.after("underwater"))
+                .with_system(underwater::god_ray_system.after("underwater")),
+        )
 }
 
 fn setup(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
     weather: Res<Weather>,
+    quality: Res<WaterQuality>,
     mut water_materials: ResMut<Assets<WaterMaterial>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut images: ResMut<Assets<Image>>,
+    windows: Res<Windows>,
+    asset_server: Res<AssetServer>,
 ) {
-    let color = Color::SEA_GREEN;
     let water = Water {
-        waves: get_waves(weather.wave_intensity),
-        wave_speed: 0.8,
-        color: color.clone(),
+        waves: weather.sea_state.waves(),
+        backend: WaveBackend::Gerstner,
+        wave_speed: weather.wave_speed,
+        color: weather.color,
+        shore: Shore::default(),
+        rogue: None,
     };
 
     let water_material = water_materials.add(WaterMaterial {
         time: 0.,
         color: water.color.into(),
         camera: Vec3::new(0., 0., 0.),
-        wave1: water.waves[0].to_vec4(),
-        wave2: water.waves[1].to_vec4(),
-        wave3: water.waves[2].to_vec4(),
+        waves: WaterWaves::new(&water, &WaveClock::default()),
+        rogue: RogueUniform::new(&water, &WaveClock::default()),
+        shore: ShoreUniform::default(),
+        fft: FftUniform::default(),
+        fft_displacement: None,
+        fft_slope: None,
+        wake: WakeUniform::new(&Wake::default()),
+        ripple: RippleUniform::default(),
+        ripple_height: None,
+        sky_rotation: Vec4::from(Quat::IDENTITY),
+        sky_map: None,
+        reflection: Some(reflection::spawn_camera(
+            &mut commands,
+            windows.primary(),
+            &mut images,
+        )),
+        rock: Some(seabed::rock_texture(&asset_server)),
     });
 
-    let mesh: Handle<Mesh> = asset_server.load("water_lod.glb#Mesh0/Primitive0");
-    let water_entity = commands
-        .spawn()
-        .insert_bundle((
-            mesh,
-            Transform::from_scale(Vec3::new(1.0, 1.0, 1.0)),
-            GlobalTransform::default(),
-            water_material,
-            // material::CustomMaterial,
-            Visibility::default(),
-            ComputedVisibility::default(),
-            water,
-            Name::new("Water"),
-        ))
-        .id();
-
-    let water_bottom_plane = commands
-        .spawn_bundle(PbrBundle {
-            mesh: meshes.add(Mesh::from(shape::Plane { size: 5000.0 })),
-            material: materials.add(color.into()),
-            transform: Transform::from_translation(Vec3::new(0.0, -1.0, 0.0)),
-            ..Default::default()
-        })
-        .insert(Name::new("Water-plane"))
-        .id();
-    commands
-        .entity(water_entity)
-        .push_children(&[water_bottom_plane]);
-}
-
-fn gerstner_wave(
-    position: Vec3,
-    time: f32,
-    target: &mut Vec3,
-    tangent: &mut Vec3,
-    binormal: &mut Vec3,
-    props: &WaveProperties,
-) -> () {
-    if props.steepness == 0. {
-        return;
-    }
-    let d = props.direction.normalize();
-
-    let position_xz = Vec2::new(position.x, position.z);
-    let k = 2. * PI / props.wavelength;
-    let c = (9.8 / k).sqrt(); // Wave speed
-    let f = k * (position_xz.dot(d) - c * time);
-    let amp_noise = 1.;
-    let a = props.steepness / k * amp_noise;
-
-    target.add_assign(Vec3::new(
-        d.x * (a * f.cos()),
-        a * f.sin() + a,
-        d.y * (a * f.cos()),
-    ));
-
-    tangent.add_assign(Vec3::new(
-        -d.x * d.x * (props.steepness * f.sin()),
-        d.x * (props.steepness * f.cos()),
-        -d.x * d.y * (props.steepness * f.sin()),
-    ));
-    binormal.add_assign(Vec3::new(
-        -d.x * d.y * (props.steepness * f.sin()),
-        d.y * (props.steepness * f.cos()),
-        -d.y * d.y * (props.steepness * f.sin()),
+    let mesh = meshes.add(mesh::clipmap(*quality));
+    commands.spawn().insert_bundle((
+        mesh,
+        Transform::from_scale(Vec3::new(1.0, 1.0, 1.0)),
+        GlobalTransform::default(),
+        water_material,
+        // material::CustomMaterial,
+        Visibility::default(),
+        ComputedVisibility::default(),
+        water,
+        Name::new("Water"),
+        RenderLayers::layer(UNREFLECTED_LAYER),
+        // the flat clipmap would shadow the waves it is displaced into
+        NotShadowCaster,
     ));
 }
 
-fn wave_sequence(position: Vec3, time: f32, waves: &[WaveProperties; 3]) -> WaveData {
-    let mut target = position.clone();
-    let mut tangent = Vec3::X;
-    let mut binormal = Vec3::Z;
-    // gerstner_wave(position, time, &mut target, &mut tangent, &mut binormal, &waves[0]);
-    // gerstner_wave(position, time, &mut target, &mut Vec3::unit_x(), &mut Vec3::unit_z(), &waves[1]);
-    // gerstner_wave(position, time, &mut target, &mut Vec3::unit_x(), &mut Vec3::unit_z(), &waves[2]);
-    for wave in waves {
-        gerstner_wave(
-            position,
-            time,
-            &mut target,
-            &mut tangent,
-            &mut binormal,
-            wave,
-        );
-    }
-    WaveData {
-        position: target,
-        normal: binormal.cross(tangent).normalize(),
-        binormal,
-        tangent,
+/// Replaces the waves with those of `sea_state` at the time of `clock`. Each new wave
+/// starts with the phase the old one had at `anchor`, so the sea there changes shape
+/// smoothly instead of jumping.
+pub fn set_waves(water: &mut Water, sea_state: &SeaState, anchor: Vec2, clock: &WaveClock) {
+    let mut waves = sea_state.waves();
+    // a different count only happens when the wind drops to nothing, nothing to jump then
+    if waves.len() == water.waves.len() {
+        let phase_at = |wave: &WaveProperties| {
+            let k = 2. * std::f32::consts::PI / wave.wavelength;
+            k * wave.direction.normalize().dot(anchor) - clock.phase(wave.omega())
+        };
+        for (new, old) in waves.iter_mut().zip(water.waves.iter()) {
+            let phase = old.phase + phase_at(old) - phase_at(new);
+            new.phase = phase.rem_euclid(2. * std::f32::consts::PI);
+        }
     }
+    water.waves = waves;
 }
 
-#[allow(dead_code)]
-pub fn set_waves(water: &mut Water, intensity: f32) -> () {
-    water.waves = get_waves(intensity);
-}
-
-pub fn get_waves(intensity: f32) -> [WaveProperties; 3] {
-    const STEEPNESS_FACTOR: f32 = 0.1;
-    [
-        WaveProperties {
-            wavelength: 60.,
-            steepness: intensity * STEEPNESS_FACTOR,
-            direction: Vec2::new(1.0, 0.0),
-        },
-        WaveProperties {
-            wavelength: 31.,
-            steepness: intensity * STEEPNESS_FACTOR,
-            direction: Vec2::new(1.0, 0.6),
-        },
-        WaveProperties {
-            wavelength: 18.,
-            steepness: intensity * STEEPNESS_FACTOR,
-            direction: Vec2::new(1.0, 1.3),
-        },
-    ]
-}
-
-pub fn surface_quat(wavedata: &WaveData) -> Quat {
-    let normal = wavedata.normal;
+pub fn surface_quat(normal: Vec3) -> Quat {
     let quat: Quat;
     if normal.y > 0.99999 {
         quat = Quat::from_xyzw(0., 0., 0., 1.);
@@ -247,59 +440,570 @@ pub fn surface_quat(wavedata: &WaveData) -> Quat {
 
 pub fn wave_probe_system(
     time: Res<Time>,
-    mut wave_probes_query: Query<(&Swimmer, &mut Transform), Without<Water>>,
+    surface: WaterSurface,
+    mut ev_splash: EventWriter<SplashEvent>,
+    mut wave_probes_query: Query<(&mut Swimmer, &mut Transform), Without<Water>>,
     water_query: Query<(&Water, &Transform), Without<Swimmer>>,
 ) {
+    for (_, mut transform) in wave_probes_query.iter_mut() {
+        let position = Vec2::new(transform.translation.x, transform.translation.z);
+        let drift = (surface.current.at(position) + surface.wake.at(position).velocity)
+            * time.delta_seconds();
+        transform.translation += Vec3::new(drift.x, 0., drift.y);
+    }
     if let Some((water, water_transform)) = water_query.iter().next() {
-        for (swimmer, mut transform) in wave_probes_query.iter_mut() {
-            let wavedata = water.wave_data_at_point(
-                Vec2::new(transform.translation.x * 1., transform.translation.z * 1.),
-                time.seconds_since_startup() as f32 * water.wave_speed,
+        let batch = water.wave_batch(&surface.clock);
+        // every hull point of every swimmer goes into one batch
+        let hull_points: Vec<Vec<Vec3>> = wave_probes_query
+            .iter()
+            .map(|(swimmer, transform)| {
+                swimmer
+                    .hull
+                    .body_points(transform.translation, transform.rotation)
+            })
+            .collect();
+        let points: Vec<Vec2> = hull_points
+            .iter()
+            .flatten()
+            .map(|point| Vec2::new(point.x, point.z))
+            .collect();
+        let surfaces: Vec<WaveData> = batch
+            .par_surface_at_points(&points, SURFACE_TOLERANCE, ComputeTaskPool::get())
+            .iter()
+            .zip(points.iter())
+            .map(|(waves, point)| {
+                let (height, slope) = surface.ripples.at(*point);
+                surface.wake.at(*point).apply(waves).lifted(height, slope)
+            })
+            .collect();
+
+        // same query, same order as the points were collected in
+        let mut surfaces = surfaces.as_slice();
+        for ((mut swimmer, mut transform), points) in wave_probes_query.iter_mut().zip(hull_points)
+        {
+            let (hull_surfaces, rest) = surfaces.split_at(points.len());
+            surfaces = rest;
+            let (falling, above) = (swimmer.velocity, transform.translation.y);
+            swimmer.float(
+                &mut transform,
+                &points,
+                hull_surfaces,
+                water_transform.translation.y,
+                time.delta_seconds(),
             );
-            transform.translation.y = wavedata.position.y + water_transform.translation.y;
 
-            transform.rotation =
-                surface_quat(&wavedata) * Quat::from_rotation_y(swimmer.world_rotation);
+            // hitting the water hard enough makes a splash
+            let surface = water_transform.translation.y
+                + hull_surfaces
+                    .iter()
+                    .map(|surface| surface.position.y)
+                    .sum::<f32>()
+                    / hull_surfaces.len().max(1) as f32;
+            if falling < -SPLASH_SPEED && above > surface && transform.translation.y <= surface {
+                ev_splash.send(SplashEvent {
+                    position: Vec2::new(transform.translation.x, transform.translation.z),
+                    strength: 0.01 * -falling * swimmer.hull.area.sqrt(),
+                    radius: swimmer.hull.area.sqrt() / 2.,
+                });
+            }
         }
     }
 }
 
-const WATER_TRANSLATE_STEP: f32 = 20.;
-fn update_system(
+fn ripple_system(
+    clock: Res<WaveClock>,
+    weather: Res<Weather>,
+    mut ripples: ResMut<Ripples>,
+    mut splashes: EventReader<SplashEvent>,
+    boat_query: Query<&Transform, With<PlayerBoat>>,
+) {
+    if let Ok(transform) = boat_query.get_single() {
+        ripples.recenter(Vec2::new(transform.translation.x, transform.translation.z));
+    }
+    for splash in splashes.iter() {
+        ripples.splash(splash);
+    }
+    ripples.update(weather.rain, &clock);
+}
+
+/// Drops the boat's wake behind its stern and lets the wakes spread.
+fn wake_system(
     time: Res<Time>,
-    mut water_material_query: Query<&Handle<WaterMaterial>>,
+    clock: Res<WaveClock>,
+    current: Res<Current>,
+    mut wake: ResMut<Wake>,
+    boat_query: Query<(&PlayerBoat, &Transform)>,
+) {
+    if let Ok((boat, transform)) = boat_query.get_single() {
+        let size = boat.hull.size();
+        let heading = Quat::from_rotation_y(boat.world_rotation) * -Vec3::Z;
+        let stern = transform.translation - heading * size.y / 2.;
+        wake.emit(
+            Vec2::new(stern.x, stern.z),
+            boat.speed,
+            size.x,
+            time.delta_seconds(),
+        );
+    }
+    wake.update(&current, &clock);
+}
+
+fn weather_system(
+    clock: Res<WaveClock>,
+    mut weather: ResMut<Weather>,
+    mut wind: ResMut<Wind>,
+    mut ev_weather: EventWriter<WeatherEvent>,
+) {
+    if let Some(event) = weather.update(&clock) {
+        ev_weather.send(event);
+    }
+    wind.update(weather.sea_state.wind_speed, &clock);
+    // the waves run with the mean wind, gusts are too short lived to raise them
+    let veer = weather
+        .sea_state
+        .wind_direction
+        .angle_between(wind.direction);
+    if veer.abs() > WAVE_VEER_STEP {
+        weather.sea_state.wind_direction = wind.direction;
+    }
+}
+
+/// Makes the waves anew once the weather has moved a step away from the sea state they
+/// were made for, or has settled.
+fn sea_state_system(
+    clock: Res<WaveClock>,
+    weather: Res<Weather>,
+    mut ev_weather: EventReader<WeatherEvent>,
+    mut applied: Local<Option<SeaState>>,
+    mut water_query: Query<&mut Water>,
+    boat_query: Query<&Transform, With<PlayerBoat>>,
+) {
+    for mut water in water_query.iter_mut() {
+        // the clock only gets faster or slower, the waves go on from where they are
+        water.wave_speed = weather.wave_speed;
+        water.color = weather.color;
+    }
+
+    let settled = ev_weather
+        .iter()
+        .any(|event| matches!(event, WeatherEvent::Settled(_)));
+    let sea_state = &weather.sea_state;
+    let stale = match &*applied {
+        Some(applied) => {
+            settled
+                || applied.wind_direction != sea_state.wind_direction
+                || (applied.wind_speed - sea_state.wind_speed).abs() > WAVE_WIND_SPEED_STEP
+        }
+        None => true,
+    };
+    if !stale {
+        return;
+    }
+    *applied = Some(sea_state.clone());
+
+    // keep the sea steady where the player is looking at it
+    let anchor = boat_query
+        .get_single()
+        .map(|transform| Vec2::new(transform.translation.x, transform.translation.z))
+        .unwrap_or(Vec2::ZERO);
+    for mut water in water_query.iter_mut() {
+        set_waves(&mut water, sea_state, anchor, &clock);
+        if let WaveBackend::Fft(ocean) = &mut water.backend {
+            ocean.set_sea_state(sea_state);
+        }
+    }
+}
+
+fn current_islands_system(
+    mut current: ResMut<Current>,
+    island_query: Query<&Transform, With<crate::WorldIsland>>,
+) {
+    // there is at most a handful of islands, cheaper than tracking which came and went
+    let islands: Vec<Vec2> = island_query
+        .iter()
+        .map(|transform| Vec2::new(transform.translation.x, transform.translation.z))
+        .collect();
+    current.set_islands(&islands);
+}
+
+fn shore_islands_system(
+    mut water_query: Query<&mut Water>,
+    island_query: Query<(&crate::WorldIsland, &Transform)>,
+) {
+    let shoals: Vec<shore::Shoal> = island_query
+        .iter()
+        .map(|(island, transform)| shore::Shoal {
+            center: Vec2::new(transform.translation.x, transform.translation.z),
+            bathymetry: island.bathymetry,
+        })
+        .collect();
+    for mut water in water_query.iter_mut() {
+        water.shore.shoals = shoals.clone();
+        // the FFT tile would run straight over the shallows and up the beach
+        if !shoals.is_empty() && matches!(water.backend, WaveBackend::Fft(_)) {
+            water.backend = WaveBackend::Gerstner;
+        }
+    }
+}
+
+fn fft_system(clock: Res<WaveClock>, mut water_query: Query<&mut Water>) {
+    for mut water in water_query.iter_mut() {
+        if let WaveBackend::Fft(ocean) = &mut water.backend {
+            ocean.update(&clock);
+        }
+    }
+}
+
+fn water_quality_system(
+    quality: Res<WaterQuality>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    water_query: Query<&Handle<Mesh>, With<Water>>,
+) {
+    if !quality.is_changed() || quality.is_added() {
+        return;
+    }
+    for handle in water_query.iter() {
+        meshes.set_untracked(handle, mesh::clipmap(*quality));
+    }
+}
+
+fn update_system(
+    surface: WaterSurface,
     mut water_mats: ResMut<Assets<WaterMaterial>>,
-    mut water_query: Query<(&mut Water, &mut Transform), Without<PlayerBoat>>,
+    mut images: ResMut<Assets<Image>>,
+    mut water_query: Query<(&Water, &Handle<WaterMaterial>, &mut Transform), Without<PlayerBoat>>,
     boat_query: Query<(&PlayerBoat, &Transform), Without<Water>>,
     camera_query: Query<(&WaterCamera, &Transform), Without<Water>>,
+    skydome: Res<SkyDome>,
 ) {
-    if let Some(mut water_material) = water_material_query
-        .get_single_mut()
-        .ok()
-        .and_then(|water_handle| water_mats.get_mut(water_handle))
-    {
-        // let mut boat_translation = Vec3::ZERO;
-        // if let Ok((_, boat_transform)) = boat_query.
-        // boat_translation = boat_transform.translation;
-        // }
-        if let Ok((water, mut water_transform)) = water_query.get_single_mut() {
-            water_material.time = time.seconds_since_startup() as f32 * water.wave_speed;
-            water_material.wave1 = water.waves[0].to_vec4();
-            water_material.wave2 = water.waves[1].to_vec4();
-            water_material.wave3 = water.waves[2].to_vec4();
-            water_material.color = water.color.into();
-
-            water_transform.translation.x = 0.;
-            if let Ok((_, boat_transform)) = boat_query.get_single() {
-                water_transform.translation.x = boat_transform.translation.x
-                    - boat_transform.translation.x % WATER_TRANSLATE_STEP;
-                water_transform.translation.z = boat_transform.translation.z
-                    - boat_transform.translation.z % WATER_TRANSLATE_STEP;
+    let (water, handle, mut water_transform) = match water_query.get_single_mut() {
+        Ok(water) => water,
+        Err(_) => return,
+    };
+    let water_material = match water_mats.get_mut(handle) {
+        Some(material) => material,
+        None => return,
+    };
+    // let mut boat_translation = Vec3::ZERO;
+    // if let Ok((_, boat_transform)) = boat_query.
+    // boat_translation = boat_transform.translation;
+    // }
+    let clock = &surface.clock;
+    // only the foam cells move with it, and they repeat every π seconds
+    water_material.time = clock.wrapped(std::f32::consts::PI);
+    water_material.waves = WaterWaves::new(water, clock);
+    water_material.rogue = RogueUniform::new(water, clock);
+    water_material.shore = ShoreUniform::new(&water.shore);
+    match &water.backend {
+        WaveBackend::Gerstner => water_material.fft = FftUniform::default(),
+        WaveBackend::Fft(ocean) => water_material.set_fft_tile(ocean, &mut images),
+    }
+    water_material.color = water.color.into();
+    water_material.wake = WakeUniform::new(&surface.wake);
+    water_material.set_ripples(&surface.ripples, &mut images);
+    // the map is in the dome's frame, turned back from where the dome is now
+    water_material.sky_rotation = Vec4::from(skydome.rotation.inverse());
+    water_material.sky_map = Some(skydome.map.clone());
+
+    // the clipmap snaps its levels in the shader, the transform just follows along
+    let center = camera_query
+        .get_single()
+        .map(|(_, transform)| transform.translation)
+        .or_else(|_| {
+            boat_query
+                .get_single()
+                .map(|(_, transform)| transform.translation)
+        });
+    if let Ok(center) = center {
+        water_transform.translation.x = center.x;
+        water_transform.translation.z = center.z;
+    }
+
+    if let Ok((_, transform)) = camera_query.get_single() {
+        water_material.camera = transform.translation;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spectrum::Spectrum;
+
+    const TIME: f32 = 3.7;
+
+    fn clock(seconds: f32) -> WaveClock {
+        let mut clock = WaveClock::default();
+        clock.step(seconds);
+        clock
+    }
+
+    fn water(backend: WaveBackend, waves: Vec<WaveProperties>) -> Water {
+        Water {
+            waves,
+            backend,
+            wave_speed: 1.,
+            color: Color::SEA_GREEN,
+            shore: Shore::default(),
+            rogue: None,
+        }
+    }
+
+    fn rough_sea() -> SeaState {
+        SeaState {
+            spectrum: Spectrum::PiersonMoskowitz,
+            wind_speed: 12.,
+            components: 32,
+            seed: 7,
+            ..Default::default()
+        }
+    }
+
+    // every displaced grid vertex is a known surface point, the query must land on it
+    fn assert_matches_displaced_grid(water: &Water) {
+        for i in -100..100 {
+            for j in -100..100 {
+                let grid_point = Vec2::new(i as f32 * 0.5, j as f32 * 0.5);
+                let surface = water.wave_data_at_point(grid_point, &clock(TIME));
+                let found = water.surface_at_point(
+                    Vec2::new(surface.position.x, surface.position.z),
+                    &clock(TIME),
+                    0.001,
+                );
+                assert!(
+                    (found.position.y - surface.position.y).abs() < 0.01,
+                    "{:?}: {} != {}",
+                    grid_point,
+                    found.position.y,
+                    surface.position.y
+                );
+                assert!(found.normal.dot(surface.normal) > 0.999, "{:?}", grid_point);
             }
         }
+    }
+
+    #[test]
+    fn gerstner_surface_matches_displaced_grid() {
+        assert_matches_displaced_grid(&water(WaveBackend::Gerstner, rough_sea().waves()));
+    }
 
-        if let Ok((_, transform)) = camera_query.get_single() {
-            water_material.camera = transform.translation;
+    #[test]
+    fn fft_surface_matches_displaced_grid() {
+        let mut ocean = FftOcean::new(&rough_sea(), 64, 128.);
+        ocean.update(&clock(TIME));
+        assert_matches_displaced_grid(&water(WaveBackend::Fft(Box::new(ocean)), vec![]));
+    }
+
+    #[test]
+    fn gerstner_surface_matches_displaced_grid_off_a_beach() {
+        let mut water = water(WaveBackend::Gerstner, rough_sea().waves());
+        water.shore.shoals.push(shore::Shoal {
+            center: Vec2::new(120., 20.),
+            bathymetry: shore::Bathymetry {
+                radius: 30.,
+                slope: 0.05,
+                depth: 25.,
+            },
+        });
+        // shoaling up to 2m deep, the surf closer in folds over like a breaker does
+        assert_matches_displaced_grid(&water);
+        // out in the lee the sea is calmer than off to the side
+        let (lee, side) = (Vec2::new(220., 50.), Vec2::new(175., 195.));
+        let swell = |point: Vec2| {
+            let heights: Vec<f32> = (0..400)
+                .map(|i| water.height_at_point(point, &clock(i as f32 * 0.5)))
+                .collect();
+            let mean = heights.iter().sum::<f32>() / heights.len() as f32;
+            heights
+                .iter()
+                .map(|h| (h - mean).powi(2))
+                .sum::<f32>()
+                .sqrt()
+        };
+        assert!(
+            swell(lee) < 0.7 * swell(side),
+            "{} {}",
+            swell(lee),
+            swell(side)
+        );
+    }
+
+    #[test]
+    fn steep_wave_height_matches_dense_cross_section() {
+        let water = water(
+            WaveBackend::Gerstner,
+            vec![WaveProperties {
+                wavelength: 20.,
+                steepness: 0.8,
+                direction: Vec2::X,
+                phase: 0.,
+            }],
+        );
+        // the wave runs along x, so the surface is a curve through (x, y) pairs
+        let section: Vec<Vec3> = (0..20000)
+            .map(|i| {
+                water
+                    .wave_data_at_point(Vec2::new(-50. + i as f32 * 0.005, 0.), &clock(TIME))
+                    .position
+            })
+            .collect();
+
+        let mut worst_naive: f32 = 0.;
+        for i in 0..400 {
+            let x = -40. + i as f32 * 0.2;
+            let segment = section
+                .windows(2)
+                .find(|pair| pair[0].x <= x && x <= pair[1].x)
+                .unwrap();
+            let t = (x - segment[0].x) / (segment[1].x - segment[0].x);
+            let expected = segment[0].y + (segment[1].y - segment[0].y) * t;
+
+            let point = Vec2::new(x, 0.);
+            let exact = water
+                .surface_at_point(point, &clock(TIME), 0.001)
+                .position
+                .y;
+            assert!(
+                (exact - expected).abs() < 0.01,
+                "x {}: {} != {}",
+                x,
+                exact,
+                expected
+            );
+            worst_naive = worst_naive
+                .max((water.wave_data_at_point(point, &clock(TIME)).position.y - expected).abs());
         }
+        // the plain displaced lookup is noticeably off on a wave this steep
+        assert!(worst_naive > 0.5, "{}", worst_naive);
+    }
+
+    #[test]
+    fn dropped_swimmer_settles_at_its_draft() {
+        let water = water(WaveBackend::Gerstner, vec![]);
+        let mut swimmer = Swimmer::default();
+        let mut transform =
+            Transform::from_xyz(3., 10., -2.).with_rotation(Quat::from_rotation_z(0.4));
+        let mut lowest = transform.translation.y;
+        for frame in 0..1200 {
+            let batch = water.wave_batch(&clock(frame as f32 / 60.));
+            let points = swimmer
+                .hull
+                .body_points(transform.translation, transform.rotation);
+            let xz: Vec<Vec2> = points.iter().map(|p| Vec2::new(p.x, p.z)).collect();
+            let surfaces = batch.surface_at_points(&xz, SURFACE_TOLERANCE);
+            swimmer.float(&mut transform, &points, &surfaces, 0., 1. / 60.);
+            lowest = lowest.min(transform.translation.y);
+        }
+        // it splashes in deeper than it floats, then comes to rest upright
+        let draft = swimmer.mass / (WATER_DENSITY * swimmer.hull.area);
+        let expected = swimmer.hull.height / 2. - draft;
+        assert!(lowest < expected - 0.5, "{}", lowest);
+        assert!(
+            (transform.translation.y - expected).abs() < 0.01,
+            "{} != {}",
+            transform.translation.y,
+            expected
+        );
+        assert!(
+            (transform.rotation * Vec3::Y).y > 0.999,
+            "{:?}",
+            transform.rotation
+        );
+    }
+
+    #[test]
+    fn changing_sea_does_not_jump_at_the_anchor() {
+        let mut sea_state = rough_sea();
+        let mut water = water(WaveBackend::Gerstner, sea_state.waves());
+        let anchor = Vec2::new(120., -40.);
+        let clock = clock(2000.);
+        let before = water.surface_at_point(anchor, &clock, 0.001).position.y;
+        // about what the wind changes by in one frame of a turning weather
+        sea_state.wind_speed += 0.01;
+        set_waves(&mut water, &sea_state, anchor, &clock);
+        let after = water.surface_at_point(anchor, &clock, 0.001).position.y;
+        assert!((after - before).abs() < 0.02, "{} -> {}", before, after);
+
+        // without shifting the phases the sea would be somewhere else entirely
+        water.waves = sea_state.waves();
+        let naive = water.surface_at_point(anchor, &clock, 0.001).position.y;
+        assert!((naive - before).abs() > 0.5, "{} -> {}", before, naive);
+    }
+
+    #[test]
+    fn foam_trails_behind_a_breaking_crest() {
+        let water = water(
+            WaveBackend::Gerstner,
+            vec![WaveProperties {
+                wavelength: 20.,
+                steepness: 0.8,
+                direction: Vec2::X,
+                phase: 0.,
+            }],
+        );
+        let k = 2. * std::f32::consts::PI / 20.;
+        let speed = (9.8 / k).sqrt();
+        // the crest, where the phase is a quarter turn, and one history step to each side
+        let crest = std::f32::consts::FRAC_PI_2 / k + speed * TIME;
+        let step = speed * foam::FOAM_HISTORY_STEP;
+        let foam = |x: f32| water.foam_at_point(Vec2::new(x, 0.), &clock(TIME));
+        let (behind, at, ahead) = (foam(crest - step), foam(crest), foam(crest + step));
+        assert!(at > 0.3, "{}", at);
+        // the crest pinched the water behind it a moment ago, ahead it has yet to come
+        assert!(behind > 0.1, "{}", behind);
+        assert!(ahead < 0.01, "{}", ahead);
+    }
+
+    #[test]
+    fn fft_foam_builds_up_and_fades() {
+        let mut ocean = FftOcean::new(&rough_sea(), 64, 128.);
+        ocean.choppiness = 2.;
+        let mut clock = clock(TIME);
+        let mut peak: f32 = 0.;
+        for _ in 0..60 {
+            clock.step(1. / 30.);
+            ocean.update(&clock);
+            peak = peak.max(ocean.foam.iter().cloned().fold(0., f32::max));
+        }
+        assert!(peak > 0.1 && peak <= 1., "{}", peak);
+
+        // without horizontal displacement nothing pinches, the foam fades away
+        ocean.choppiness = 0.;
+        for _ in 0..10 {
+            clock.step(1.);
+            ocean.update(&clock);
+        }
+        assert!(ocean.foam.iter().all(|foam| *foam < 0.01));
+    }
+
+    #[test]
+    fn wind_and_wake_keep_to_the_clock() {
+        let current = Current::default();
+        let mut clock = WaveClock::default();
+        let mut wind = Wind::new(Vec2::X, 10.);
+        let mut wake = Wake::default();
+        wake.emit(Vec2::ZERO, 4., 3., 1.);
+        let point = Vec2::ZERO;
+        wind.update(10., &clock);
+        wake.update(&current, &clock);
+        let gust = wind.at(point);
+        let ring = wake.at(Vec2::new(1., 0.)).height;
+
+        // nothing moves on while the clock stands still
+        wind.update(10., &clock);
+        wake.update(&current, &clock);
+        assert_eq!(wind.at(point), gust);
+        assert_eq!(wake.at(Vec2::new(1., 0.)).height, ring);
+
+        // at three times the speed a real second is three of wave time, for both
+        clock.scale = 3.;
+        clock.tick(1., 1.);
+        wind.update(10., &clock);
+        wake.update(&current, &clock);
+        assert!((wake.sources[0].age - 3.).abs() < 1e-5);
+        // the gust that was at the point has rolled 30m downwind, while the mean wind
+        // veered a little
+        let moved = wind.at(point + wind.direction * 30.).length() - gust.length();
+        assert!(moved.abs() < 1e-3, "{}", moved);
+        assert!((wind.at(point).length() - gust.length()).abs() > 1.);
     }
 }
@@ -0,0 +1,137 @@
+use super::underwater::Underwater;
+use super::{Water, WaterCamera};
+use bevy::core_pipeline::clear_color::ClearColorConfig;
+use bevy::prelude::*;
+use bevy::render::camera::RenderTarget;
+use bevy::render::render_resource::{
+    Extent3d, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+};
+use bevy::render::view::RenderLayers;
+use bevy::window::{WindowId, WindowResized};
+
+/// Render layer of what the reflection camera leaves out: the water, what is under it
+/// and the sky dome, which the water reflects from the sky map instead.
+pub const UNREFLECTED_LAYER: u8 = 1;
+
+/// Renders the scene mirrored under the water, for the water to reflect what is close by.
+#[derive(Component)]
+pub struct ReflectionCamera;
+
+/// Image the reflection camera renders into, half the window's resolution. The water
+/// samples it in screen space so it only has to keep the window's aspect.
+fn target(window: &Window, images: &mut Assets<Image>) -> Handle<Image> {
+    let size = target_size(
+        window.width(),
+        window.height(),
+        window.scale_factor_override(),
+    );
+    let mut image = Image {
+        texture_descriptor: TextureDescriptor {
+            label: Some("water_reflection"),
+            size,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Bgra8UnormSrgb,
+            mip_level_count: 1,
+            sample_count: 1,
+            usage: TextureUsages::TEXTURE_BINDING
+                | TextureUsages::COPY_DST
+                | TextureUsages::RENDER_ATTACHMENT,
+        },
+        ..Default::default()
+    };
+    image.resize(size);
+    images.add(image)
+}
+
+fn target_size(width: f32, height: f32, scale_factor_override: Option<f64>) -> Extent3d {
+    let scale = scale_factor_override.unwrap_or(1.) as f32 / 2.;
+    Extent3d {
+        width: (width * scale).max(1.) as u32,
+        height: (height * scale).max(1.) as u32,
+        depth_or_array_layers: 1,
+    }
+}
+
+/// Spawns the reflection camera and returns the image it renders into.
+pub fn spawn_camera(
+    commands: &mut Commands,
+    window: &Window,
+    images: &mut Assets<Image>,
+) -> Handle<Image> {
+    let image = target(window, images);
+    commands
+        .spawn_bundle(Camera3dBundle {
+            camera: Camera {
+                // before the main camera, which shows it on the water
+                priority: -1,
+                target: RenderTarget::Image(image.clone()),
+                ..Default::default()
+            },
+            camera_3d: Camera3d {
+                // transparent where nothing was drawn, the sky map shows there
+                clear_color: ClearColorConfig::Custom(Color::NONE),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(UiCameraConfig { show_ui: false })
+        .insert(RenderLayers::layer(0))
+        .insert(ReflectionCamera)
+        .insert(Name::new("Water-reflection-camera"));
+    image
+}
+
+/// Puts the reflection camera at the mirror image of the main camera under the water.
+/// Keeping its up as +Y instead of mirroring it turns the image upside down, which the
+/// water shader undoes.
+pub fn reflection_system(
+    underwater: Res<Underwater>,
+    water_query: Query<&GlobalTransform, With<Water>>,
+    camera_query: Query<&Transform, (With<WaterCamera>, Without<ReflectionCamera>)>,
+    mut reflection_query: Query<(&mut Camera, &mut Transform), With<ReflectionCamera>>,
+) {
+    let (level, camera) = match (water_query.get_single(), camera_query.get_single()) {
+        (Ok(water), Ok(camera)) => (water.translation().y, camera),
+        _ => return,
+    };
+    for (mut reflection, mut transform) in reflection_query.iter_mut() {
+        // nothing of it shows from below
+        reflection.is_active = underwater.amount < 1.;
+        let mut forward = camera.forward();
+        if forward.y.abs() > 0.999 {
+            continue;
+        }
+        forward.y = -forward.y;
+        let mut translation = camera.translation;
+        translation.y = 2. * level - translation.y;
+        *transform =
+            Transform::from_translation(translation).looking_at(translation + forward, Vec3::Y);
+    }
+}
+
+/// Keeps the reflection image at half the window's size, or the water would sample it
+/// stretched once the window changes shape.
+pub fn resize_system(
+    mut events: EventReader<WindowResized>,
+    windows: Res<Windows>,
+    reflection_query: Query<&Camera, With<ReflectionCamera>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    // only the latest size matters
+    let resized = match events.iter().rfind(|event| event.id == WindowId::primary()) {
+        Some(event) => event,
+        None => return,
+    };
+    let scale_factor_override = windows
+        .get(resized.id)
+        .and_then(|window| window.scale_factor_override());
+    let size = target_size(resized.width, resized.height, scale_factor_override);
+    for reflection in reflection_query.iter() {
+        if let RenderTarget::Image(handle) = &reflection.target {
+            match images.get_mut(handle) {
+                Some(image) if image.texture_descriptor.size != size => image.resize(size),
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use super::clock::WaveClock;
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Cells per side of the ripple grid, a power of two like the FFT tile.
+const SIZE: usize = 128;
+/// m per cell, the grid covers 32m around the boat.
+const CELL: f32 = 0.25;
+/// m/s ripples travel at.
+const SPEED: f32 = 1.5;
+/// Fraction of the ripples lost per second.
+const DAMPING: f32 = 0.4;
+/// Cells along the border where ripples die out instead of bouncing back.
+const SPONGE: usize = 8;
+/// Seconds per simulation step, long frames take several.
+const STEP: f32 = 1. / 60.;
+const MAX_STEPS: usize = 4;
+/// m a raindrop pushes the water down by.
+const RAINDROP: f32 = 0.004;
+
+/// Something hit the water at `position` in world xz.
+#[derive(Debug, Clone, Copy)]
+pub struct SplashEvent {
+    pub position: Vec2,
+    /// m the water is pushed down in the middle, scales with the hit
+    pub strength: f32,
+    /// m across the splash
+    pub radius: f32,
+}
+
+/// Small ripples on top of the waves, a wave equation on a grid that follows the boat.
+/// Splashes, the paddle and rain stir it up.
+pub struct Ripples {
+    /// world cell of the grid's first texel, the grid only ever moves by whole cells so
+    /// the ripples stay where they are
+    pub origin: IVec2,
+    /// m up or down per cell, row major with z as row
+    pub height: Vec<f32>,
+    previous: Vec<f32>,
+    /// seconds not simulated yet
+    pending: f32,
+    /// wave time of the last update
+    last: f64,
+    rng: StdRng,
+}
+
+impl Default for Ripples {
+    fn default() -> Self {
+        Ripples {
+            origin: IVec2::ZERO,
+            height: vec![0.; SIZE * SIZE],
+            previous: vec![0.; SIZE * SIZE],
+            pending: 0.,
+            last: 0.,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+}
+
+impl Ripples {
+    pub const SIZE: usize = SIZE;
+    pub const CELL: f32 = CELL;
+
+    /// World xz of the first texel.
+    pub fn corner(&self) -> Vec2 {
+        self.origin.as_vec2() * CELL
+    }
+
+    /// Moves the grid to have `center` in the middle, keeping the ripples in the world.
+    pub fn recenter(&mut self, center: Vec2) {
+        let origin = (center / CELL).floor().as_ivec2() - IVec2::splat(SIZE as i32 / 2);
+        let shift = origin - self.origin;
+        if shift == IVec2::ZERO {
+            return;
+        }
+        self.origin = origin;
+        for field in [&mut self.height, &mut self.previous] {
+            let old = std::mem::replace(field, vec![0.; SIZE * SIZE]);
+            for row in 0..SIZE as i32 {
+                for column in 0..SIZE as i32 {
+                    let (from_column, from_row) = (column + shift.x, row + shift.y);
+                    if (0..SIZE as i32).contains(&from_column)
+                        && (0..SIZE as i32).contains(&from_row)
+                    {
+                        field[(row * SIZE as i32 + column) as usize] =
+                            old[(from_row * SIZE as i32 + from_column) as usize];
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pushes the water down in a round dent, splashes off the grid are lost.
+    pub fn splash(&mut self, splash: &SplashEvent) {
+        let center = (splash.position - self.corner()) / CELL;
+        let reach = (splash.radius / CELL).ceil() as i32 + 1;
+        let sigma = (splash.radius / 2.).max(CELL);
+        for row in center.y as i32 - reach..=center.y as i32 + reach {
+            for column in center.x as i32 - reach..=center.x as i32 + reach {
+                if !(0..SIZE as i32).contains(&row) || !(0..SIZE as i32).contains(&column) {
+                    continue;
+                }
+                let distance = (Vec2::new(column as f32, row as f32) - center).length() * CELL;
+                self.height[row as usize * SIZE + column as usize] -=
+                    splash.strength * (-(distance / sigma).powi(2)).exp();
+            }
+        }
+    }
+
+    /// Rains `rain` drops per m² and second on the grid and runs it on to the time of
+    /// `clock`.
+    pub fn update(&mut self, rain: f32, clock: &WaveClock) {
+        let delta = clock.since(&mut self.last);
+        self.rain(rain, delta);
+        self.simulate(delta);
+    }
+
+    /// Drops `rain` drops per m² and second on the grid for `delta` seconds.
+    fn rain(&mut self, rain: f32, delta: f32) {
+        let area = (SIZE as f32 * CELL).powi(2);
+        let expected = rain * area * delta;
+        // whole drops, the fraction left is a chance for one more
+        let drops = expected as usize + (self.rng.gen::<f32>() < expected.fract()) as usize;
+        for _ in 0..drops {
+            let i = self.rng.gen_range(0, SIZE * SIZE);
+            self.height[i] -= RAINDROP;
+        }
+    }
+
+    /// Runs the wave equation for `delta` more seconds, in fixed steps.
+    fn simulate(&mut self, delta: f32) {
+        self.pending = (self.pending + delta).min(STEP * MAX_STEPS as f32);
+        while self.pending >= STEP {
+            self.pending -= STEP;
+            self.step();
+        }
+    }
+
+    fn step(&mut self) {
+        let courant = (SPEED * STEP / CELL).powi(2);
+        let keep = 1. - DAMPING * STEP;
+        let height = &self.height;
+        let mut next = std::mem::take(&mut self.previous);
+        for row in 1..SIZE - 1 {
+            for column in 1..SIZE - 1 {
+                let i = row * SIZE + column;
+                let laplacian = height[i - 1] + height[i + 1] + height[i - SIZE] + height[i + SIZE]
+                    - 4. * height[i];
+                let border = row.min(column).min(SIZE - 1 - row).min(SIZE - 1 - column);
+                let sponge = if border < SPONGE {
+                    border as f32 / SPONGE as f32
+                } else {
+                    1.
+                };
+                next[i] = ((2. * height[i] - next[i]) * keep + courant * laplacian) * sponge;
+            }
+        }
+        self.previous = std::mem::replace(&mut self.height, next);
+    }
+
+    /// Height and (dh/dx, dh/dz) at `point` in world xz, nothing outside the grid.
+    pub fn at(&self, point: Vec2) -> (f32, Vec2) {
+        let texel = (point - self.corner()) / CELL;
+        let base = texel.floor();
+        let (column, row) = (base.x as i32, base.y as i32);
+        if column < 0 || row < 0 || column >= SIZE as i32 - 1 || row >= SIZE as i32 - 1 {
+            return (0., Vec2::ZERO);
+        }
+        let t = texel - base;
+        let i = row as usize * SIZE + column as usize;
+        let (h00, h10) = (self.height[i], self.height[i + 1]);
+        let (h01, h11) = (self.height[i + SIZE], self.height[i + SIZE + 1]);
+        let height =
+            (h00 * (1. - t.x) + h10 * t.x) * (1. - t.y) + (h01 * (1. - t.x) + h11 * t.x) * t.y;
+        let slope = Vec2::new(
+            (h10 - h00) * (1. - t.y) + (h11 - h01) * t.y,
+            (h01 - h00) * (1. - t.x) + (h11 - h10) * t.x,
+        ) / CELL;
+        (height, slope)
+    }
+
+    /// Grid packed for the water shader: height in x, slopes in yz.
+    pub fn texture_data(&self) -> Vec<[f32; 4]> {
+        (0..SIZE * SIZE)
+            .map(|i| {
+                let (row, column) = (i / SIZE, i % SIZE);
+                let at = |row: usize, column: usize| self.height[row * SIZE + column];
+                let dx = at(row, (column + 1).min(SIZE - 1)) - at(row, column.saturating_sub(1));
+                let dz = at((row + 1).min(SIZE - 1), column) - at(row.saturating_sub(1), column);
+                [self.height[i], dx / (2. * CELL), dz / (2. * CELL), 0.]
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splash_spreads_out_and_dies_down() {
+        let mut ripples = Ripples::default();
+        ripples.recenter(Vec2::new(100., -30.));
+        let center = Vec2::new(100., -30.);
+        ripples.splash(&SplashEvent {
+            position: center,
+            strength: 0.1,
+            radius: 0.5,
+        });
+        ripples.simulate(2. * STEP);
+        let ring = |ripples: &Ripples, radius: f32| {
+            (0..32)
+                .map(|i| {
+                    let angle = i as f32 / 32. * std::f32::consts::TAU;
+                    ripples
+                        .at(center + Vec2::from_angle(angle) * radius)
+                        .0
+                        .abs()
+                })
+                .fold(0., f32::max)
+        };
+        assert!(ring(&ripples, 5.) < 1e-4);
+        for _ in 0..60 {
+            ripples.simulate(STEP);
+        }
+        // a second later the front is 1.5m out
+        assert!(ring(&ripples, 1.5) > 10. * ring(&ripples, 5.));
+
+        // moving the grid keeps the ripples where they were in the world
+        let before = ripples.at(center + Vec2::new(1., 0.5));
+        ripples.recenter(center + Vec2::new(3., -2.));
+        let after = ripples.at(center + Vec2::new(1., 0.5));
+        assert!(
+            (before.0 - after.0).abs() < 1e-6,
+            "{:?} {:?}",
+            before,
+            after
+        );
+
+        for _ in 0..30 * 60 {
+            ripples.simulate(STEP);
+        }
+        assert!(ripples.height.iter().all(|h| h.abs() < 1e-4));
+    }
+}
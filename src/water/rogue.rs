@@ -0,0 +1,394 @@
+use super::spectrum::GRAVITY;
+use super::{Water, WaveBackend, WaveClock, WaveProperties, Wind, MAX_WAVES};
+use crate::boat::PlayerBoat;
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+/// Waves a rogue wave is focused from, `Water::sea_waves` makes room for them.
+pub const ROGUE_COMPONENTS: usize = 8;
+/// Summed steepness the components reach at the focus at most.
+const MAX_STEEPNESS: f32 = 0.5;
+/// Summed steepness the sea and the rogue wave together stay below 1 by, where the crests
+/// would loop over themselves.
+const STEEPNESS_MARGIN: f32 = 0.1;
+/// Seconds of wave time from the warning to the wave standing up at its focus.
+const LEAD: f32 = 60.;
+/// Seconds of wave time it takes to fade in after the warning, and out after the focus.
+const FADE: f32 = 20.;
+/// Seconds of wave time after the focus until the wave is gone.
+const AFTERMATH: f32 = 60.;
+/// Peak wavelengths of the packet along and across its way.
+const PACKET_LENGTH: f32 = 2.;
+const PACKET_WIDTH: f32 = 6.;
+/// Seconds of wave time between rogue waves on average, in a wind of `ROUGH_WIND`.
+const MEAN_INTERVAL: f32 = 900.;
+/// m/s of wind, rogue waves turn up more often in rougher seas.
+const ROUGH_WIND: f32 = 20.;
+/// m around the boat a rogue wave stands up.
+const FOCUS_SPREAD: f32 = 60.;
+
+/// A single huge wave, a group of waves that all crest together at `focus` at
+/// `focus_time` and run out of step everywhere else. Its envelope keeps it to a packet
+/// that travels at the group speed, fading in before and out after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RogueWave {
+    pub seed: u64,
+    /// extra waves added to the sea's, phased to crest together at the focus
+    pub waves: Vec<WaveProperties>,
+    pub focus: Vec2,
+    /// where it runs, normalized
+    pub direction: Vec2,
+    /// m from the mean level up to the crest at the focus
+    pub height: f32,
+    pub peak_wavelength: f32,
+    /// wave time when it stands up at its focus
+    pub focus_time: f64,
+    /// wave time of the warning
+    pub warned_time: f64,
+}
+
+/// Where a rogue wave's packet is at one moment, see `RogueWave::envelope`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Envelope {
+    pub center: Vec2,
+    pub direction: Vec2,
+    /// m along the way the packet falls to 1/e
+    pub length: f32,
+    /// m across the way the packet falls to 1/e
+    pub width: f32,
+    /// from 0 to 1, fading in and out over time
+    pub strength: f32,
+}
+
+impl Envelope {
+    /// How much of the rogue wave there is at `point`, from 0 to 1. Same as
+    /// rogue_envelope in water.wgsl, keep both in sync.
+    pub fn at(&self, point: Vec2) -> f32 {
+        let offset = point - self.center;
+        let along = self.direction.dot(offset) / self.length;
+        let across = self.direction.perp_dot(offset) / self.width;
+        self.strength * (-along * along - across * across).exp()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RogueWaveEvent {
+    /// A rogue wave will stand up at `focus` in `seconds` of wave time.
+    Warning {
+        focus: Vec2,
+        direction: Vec2,
+        height: f32,
+        seconds: f32,
+    },
+    /// It has run out and is gone.
+    Passed,
+}
+
+impl RogueWave {
+    /// Draws a rogue wave from `seed`, running along `direction` and standing up around
+    /// `near` `LEAD` seconds after the time of `clock`. The same seed always makes the
+    /// same wave, as steep as a sea of `sea_steepness` summed leaves room for.
+    pub fn new(
+        seed: u64,
+        near: Vec2,
+        direction: Vec2,
+        sea_steepness: f32,
+        clock: &WaveClock,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let turn = rng.gen_range(-0.3, 0.3);
+        let direction = Vec2::from_angle(turn).rotate(direction.normalize());
+        let focus = near
+            + Vec2::new(
+                rng.gen_range(-FOCUS_SPREAD, FOCUS_SPREAD),
+                rng.gen_range(-FOCUS_SPREAD, FOCUS_SPREAD),
+            );
+        let height = rng.gen_range(12., 18.);
+        let peak_wavelength = rng.gen_range(70., 110.);
+        let focus_time = clock.seconds() + LEAD as f64;
+
+        // the crest of y = a sin(f) + a is 2a above the mean level
+        let amplitude = height / (2. * ROGUE_COMPONENTS as f32);
+        let mut waves: Vec<WaveProperties> = (0..ROGUE_COMPONENTS)
+            .map(|i| {
+                let t = i as f32 / (ROGUE_COMPONENTS - 1) as f32;
+                let wavelength = peak_wavelength * (0.8 + 0.45 * t);
+                let spread = rng.gen_range(-0.15, 0.15);
+                WaveProperties {
+                    wavelength,
+                    steepness: 2. * PI / wavelength * amplitude,
+                    direction: Vec2::from_angle(spread).rotate(direction),
+                    phase: 0.,
+                }
+            })
+            .collect();
+        let total: f32 = waves.iter().map(|wave| wave.steepness).sum();
+        let room = (1. - sea_steepness - STEEPNESS_MARGIN).clamp(0., MAX_STEEPNESS);
+        let scale = (room / total).min(1.);
+        // all crests meet at the focus: f = k (d . focus) + phase - ω t_f = π/2
+        let mut at_focus = clock.clone();
+        at_focus.step(LEAD);
+        for wave in waves.iter_mut() {
+            wave.steepness *= scale;
+            let k = 2. * PI / wave.wavelength;
+            let phase = FRAC_PI_2 - k * wave.direction.dot(focus) + at_focus.phase(wave.omega());
+            wave.phase = phase.rem_euclid(TAU);
+        }
+
+        RogueWave {
+            seed,
+            waves,
+            focus,
+            direction,
+            height: height * scale,
+            peak_wavelength,
+            focus_time,
+            warned_time: clock.seconds(),
+        }
+    }
+
+    pub fn warning(&self, clock: &WaveClock) -> RogueWaveEvent {
+        RogueWaveEvent::Warning {
+            focus: self.focus,
+            direction: self.direction,
+            height: self.height,
+            seconds: self.seconds_left(clock),
+        }
+    }
+
+    /// Seconds of wave time from the time of `clock` until it stands up.
+    pub fn seconds_left(&self, clock: &WaveClock) -> f32 {
+        (self.focus_time - clock.seconds()) as f32
+    }
+
+    /// The packet at the time of `clock`. It comes in at the group speed of its peak
+    /// wavelength, half the speed of the crests running through it.
+    pub fn envelope(&self, clock: &WaveClock) -> Envelope {
+        let since_focus = (clock.seconds() - self.focus_time) as f32;
+        let since_warning = (clock.seconds() - self.warned_time) as f32;
+        let k = 2. * PI / self.peak_wavelength;
+        let group_speed = 0.5 * (GRAVITY / k).sqrt();
+        let fade_in = (since_warning / FADE).clamp(0., 1.);
+        let fade_out = ((AFTERMATH - since_focus) / FADE).clamp(0., 1.);
+        Envelope {
+            center: self.focus + self.direction * group_speed * since_focus,
+            direction: self.direction,
+            length: PACKET_LENGTH * self.peak_wavelength,
+            width: PACKET_WIDTH * self.peak_wavelength,
+            strength: fade_in * fade_out,
+        }
+    }
+
+    /// Run out by the time of `clock`.
+    pub fn passed(&self, clock: &WaveClock) -> bool {
+        clock.seconds() > self.focus_time + AFTERMATH as f64
+    }
+}
+
+/// When the next rogue wave comes, at random or on cue.
+pub struct RogueSea {
+    /// seconds of wave time until the next one, in a wind of `ROUGH_WIND`
+    remaining: f32,
+    /// wave time of the last update
+    last: f64,
+    /// seeds of rogue waves asked for with `schedule`, they come first
+    scheduled: Vec<u64>,
+    rng: StdRng,
+}
+
+impl Default for RogueSea {
+    fn default() -> Self {
+        RogueSea::new(0)
+    }
+}
+
+impl RogueSea {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        RogueSea {
+            remaining: Self::interval(&mut rng),
+            last: 0.,
+            scheduled: vec![],
+            rng,
+        }
+    }
+
+    fn interval(rng: &mut StdRng) -> f32 {
+        let roll: f32 = rng.gen_range(f32::EPSILON, 1.);
+        -roll.ln() * MEAN_INTERVAL
+    }
+
+    /// Sends the rogue wave of `seed` next, as soon as the sea is free of the last one.
+    #[allow(dead_code)]
+    pub fn schedule(&mut self, seed: u64) {
+        self.scheduled.push(seed);
+    }
+
+    /// Lets the time up to `clock` go by without any rogue wave coming due.
+    pub fn idle(&mut self, clock: &WaveClock) {
+        self.last = clock.seconds();
+    }
+
+    /// Advances to the time of `clock` in a wind of `wind_speed`, returns the seed of a
+    /// rogue wave once one is due.
+    pub fn update(&mut self, clock: &WaveClock, wind_speed: f32) -> Option<u64> {
+        let delta = clock.since(&mut self.last);
+        if !self.scheduled.is_empty() {
+            return Some(self.scheduled.remove(0));
+        }
+        let roughness = (wind_speed / ROUGH_WIND).powi(2);
+        self.remaining -= delta * roughness;
+        if self.remaining > 0. {
+            return None;
+        }
+        self.remaining = Self::interval(&mut self.rng);
+        Some(self.rng.gen())
+    }
+}
+
+/// Sends rogue waves through the Gerstner waves near the boat, one at a time, warning
+/// ahead of each. The FFT tile can not show them, so none come while it is on and the
+/// one on its way is called off.
+pub fn rogue_system(
+    clock: Res<WaveClock>,
+    wind: Res<Wind>,
+    mut rogue_sea: ResMut<RogueSea>,
+    mut ev_rogue: EventWriter<RogueWaveEvent>,
+    mut water_query: Query<&mut Water>,
+    boat_query: Query<&Transform, With<PlayerBoat>>,
+) {
+    for mut water in water_query.iter_mut() {
+        let fft = matches!(water.backend, WaveBackend::Fft(_));
+        if matches!(&water.rogue, Some(rogue) if fft || rogue.passed(&clock)) {
+            water.rogue = None;
+            ev_rogue.send(RogueWaveEvent::Passed);
+        }
+        if fft {
+            rogue_sea.idle(&clock);
+            continue;
+        }
+        if water.rogue.is_some() {
+            continue;
+        }
+        if let Some(seed) = rogue_sea.update(&clock, wind.speed) {
+            let near = boat_query
+                .get_single()
+                .map(|transform| Vec2::new(transform.translation.x, transform.translation.z))
+                .unwrap_or(Vec2::ZERO);
+            // the sea's waves that make room for it, see `Water::sea_waves`
+            let sea_steepness = water
+                .waves
+                .iter()
+                .take(MAX_WAVES - ROGUE_COMPONENTS)
+                .map(|wave| wave.steepness)
+                .sum();
+            let rogue = RogueWave::new(seed, near, wind.direction, sea_steepness, &clock);
+            ev_rogue.send(rogue.warning(&clock));
+            water.rogue = Some(rogue);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::spectrum::SeaState;
+    use super::*;
+
+    const NEAR: Vec2 = Vec2::new(300., -200.);
+
+    fn clock() -> WaveClock {
+        let mut clock = WaveClock::default();
+        clock.step(1234.5);
+        clock
+    }
+
+    fn water(waves: Vec<WaveProperties>, rogue: &RogueWave) -> Water {
+        Water {
+            waves,
+            backend: WaveBackend::Gerstner,
+            wave_speed: 1.,
+            color: Color::BLACK,
+            shore: Default::default(),
+            rogue: Some(rogue.clone()),
+        }
+    }
+
+    fn height(water: &Water, clock: &WaveClock, point: Vec2) -> f32 {
+        water.surface_at_point(point, clock, 0.01).position.y
+    }
+
+    fn steepness(waves: &[WaveProperties]) -> f32 {
+        waves.iter().map(|wave| wave.steepness).sum()
+    }
+
+    #[test]
+    fn same_seed_same_rogue_wave() {
+        let rogue = RogueWave::new(7, NEAR, Vec2::X, 0., &clock());
+        assert_eq!(rogue, RogueWave::new(7, NEAR, Vec2::X, 0., &clock()));
+        assert_ne!(rogue, RogueWave::new(8, NEAR, Vec2::X, 0., &clock()));
+    }
+
+    #[test]
+    fn warning_counts_down_to_the_focus() {
+        let mut clock = clock();
+        let rogue = RogueWave::new(7, NEAR, Vec2::X, 0., &clock);
+        let seconds = |clock: &WaveClock| match rogue.warning(clock) {
+            RogueWaveEvent::Warning { seconds, .. } => seconds,
+            RogueWaveEvent::Passed => panic!("no warning"),
+        };
+        assert_eq!(seconds(&clock), LEAD);
+        clock.step(15.);
+        assert!((seconds(&clock) - (LEAD - 15.)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rogue_wave_stands_up_at_its_focus_only() {
+        let clock = clock();
+        let rogue = RogueWave::new(7, NEAR, Vec2::X, 0., &clock);
+        let water = water(vec![], &rogue);
+        let mut at_focus = clock.clone();
+        at_focus.step(LEAD);
+        let peak = height(&water, &at_focus, rogue.focus);
+        assert!(peak > 0.9 * rogue.height, "{} {}", peak, rogue.height);
+        // nothing much two packet lengths off, or at the focus at the warning
+        let off = rogue.focus + rogue.direction * 2. * PACKET_LENGTH * rogue.peak_wavelength;
+        assert!(height(&water, &at_focus, off) < 0.1 * rogue.height);
+        assert!(height(&water, &clock, rogue.focus) < 0.1 * rogue.height);
+    }
+
+    #[test]
+    fn rogue_wave_leaves_a_rough_sea_room() {
+        let clock = clock();
+        let sea = SeaState {
+            wind_speed: 20.,
+            components: 64,
+            ..Default::default()
+        }
+        .waves();
+        let sea_steepness = steepness(&sea[..MAX_WAVES - ROGUE_COMPONENTS]);
+        let rogue = RogueWave::new(7, NEAR, Vec2::X, sea_steepness, &clock);
+        let total = sea_steepness + steepness(&rogue.waves);
+        assert!(total <= 1. - STEEPNESS_MARGIN + 1e-4, "{}", total);
+        assert!(rogue.height < RogueWave::new(7, NEAR, Vec2::X, 0., &clock).height);
+
+        // still standing up out of the sea at its focus
+        let mut calm = water(sea.clone(), &rogue);
+        calm.rogue = None;
+        let water = water(sea, &rogue);
+        let mut at_focus = clock.clone();
+        at_focus.step(LEAD);
+        let rise = height(&water, &at_focus, rogue.focus) - height(&calm, &at_focus, rogue.focus);
+        assert!(rise > 0.5 * rogue.height, "{} {}", rise, rogue.height);
+    }
+
+    #[test]
+    fn rogue_wave_passes_after_its_aftermath() {
+        let mut clock = clock();
+        let rogue = RogueWave::new(7, NEAR, Vec2::X, 0., &clock);
+        clock.step(LEAD);
+        assert!(!rogue.passed(&clock));
+        clock.step(AFTERMATH + 1.);
+        assert!(rogue.passed(&clock));
+    }
+}
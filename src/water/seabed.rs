@@ -0,0 +1,106 @@
+use super::material::ShoreUniform;
+use super::reflection::UNREFLECTED_LAYER;
+use super::underwater::Underwater;
+use super::{mesh, Water, WaterCamera, WaveClock};
+use bevy::pbr::NotShadowCaster;
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::render_resource::{AsBindGroup, ShaderRef};
+use bevy::render::view::{NoFrustumCulling, RenderLayers};
+use std::f32::consts::PI;
+
+/// m between vertices of the seabed grid.
+const CELL: f32 = 1.5;
+/// Cells along a side of the grid. It follows the camera, under water the fog hides its
+/// edge long before, and from above only the beaches stick out of the water.
+const CELLS: u32 = 160;
+/// Seconds before the caustics' layers all line up as they started, see seabed.wgsl.
+const CAUSTICS_PERIOD: f32 = 96. * PI;
+
+/// The sea floor around the camera, shaped in seabed.wgsl from the islands' shoals: deep
+/// ocean far from them, a shelf rising to reefs and beaches around them.
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "5f187b60-4078-411f-92bf-fd432ae04bec"]
+pub struct SeabedMaterial {
+    #[uniform(0)]
+    pub time: f32,
+    /// 0 above water up to 1 below, the caustics show up once under
+    #[uniform(1)]
+    pub amount: f32,
+    #[uniform(2)]
+    pub shore: ShoreUniform,
+    #[texture(3)]
+    #[sampler(4)]
+    pub rock: Option<Handle<Image>>,
+}
+
+impl Material for SeabedMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/seabed.wgsl".into()
+    }
+    fn fragment_shader() -> ShaderRef {
+        "shaders/seabed.wgsl".into()
+    }
+}
+
+#[derive(Component)]
+pub struct Seabed;
+
+/// Texture of the reefs' rocks, also seen from above through the water.
+pub fn rock_texture(asset_server: &AssetServer) -> Handle<Image> {
+    asset_server.load("textures/mossy.jpg")
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<SeabedMaterial>>,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn_bundle(MaterialMeshBundle {
+            mesh: meshes.add(mesh::grid(CELLS, CELL)),
+            material: materials.add(SeabedMaterial {
+                time: 0.,
+                amount: 0.,
+                shore: ShoreUniform::default(),
+                rock: Some(rock_texture(&asset_server)),
+            }),
+            ..Default::default()
+        })
+        .insert(Seabed)
+        .insert(Name::new("Seabed"))
+        .insert(RenderLayers::layer(UNREFLECTED_LAYER))
+        // the grid is flat until the shader moves it down onto the floor
+        .insert(NoFrustumCulling)
+        .insert(NotShadowCaster);
+}
+
+pub fn seabed_system(
+    clock: Res<WaveClock>,
+    underwater: Res<Underwater>,
+    water_query: Query<(&Water, &GlobalTransform)>,
+    camera_query: Query<&Transform, With<WaterCamera>>,
+    mut seabed_query: Query<&mut Transform, (With<Seabed>, Without<WaterCamera>)>,
+    material_query: Query<&Handle<SeabedMaterial>, With<Seabed>>,
+    mut materials: ResMut<Assets<SeabedMaterial>>,
+) {
+    let (water, water_transform) = match water_query.get_single() {
+        Ok(water) => water,
+        Err(_) => return,
+    };
+    if let Ok(camera) = camera_query.get_single() {
+        // snapped to whole cells, the vertices stay put on the floor
+        let center = (Vec2::new(camera.translation.x, camera.translation.z) / CELL).round() * CELL;
+        for mut transform in seabed_query.iter_mut() {
+            transform.translation = Vec3::new(center.x, water_transform.translation().y, center.y);
+        }
+    }
+    for handle in material_query.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.time = clock.wrapped(CAUSTICS_PERIOD);
+            material.amount = underwater.amount;
+            material.shore = ShoreUniform::new(&water.shore);
+        }
+    }
+}
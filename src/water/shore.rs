@@ -0,0 +1,344 @@
+use bevy::prelude::*;
+
+/// Islands shaping the waves at once, the water shader's shoal array has this size.
+pub const MAX_SHOALS: usize = 4;
+/// Waves break once they are this fraction of the depth high.
+const BREAKER_INDEX: f32 = 0.78;
+/// How many times steeper than out at sea a wave can get before it breaks.
+const MAX_STEEPENING: f32 = 3.;
+/// Past k * depth = 4 the water is deep for a wave, tanh(4) is 0.9993.
+const DEEP: f32 = 4.;
+/// Island radii the calm in the lee reaches downwave, before the waves bending around
+/// the island fill it in again.
+const LEE_LENGTH: f32 = 6.;
+
+/// The sea floor around a round island, rising evenly from the open sea to the beach.
+#[derive(Debug, Clone, Copy)]
+pub struct Bathymetry {
+    /// m from the center to the beach
+    pub radius: f32,
+    /// m of depth gained per m out from the beach
+    pub slope: f32,
+    /// m of the open sea floor around it
+    pub depth: f32,
+}
+
+/// A `Bathymetry` somewhere in the world.
+#[derive(Debug, Clone, Copy)]
+pub struct Shoal {
+    pub center: Vec2,
+    pub bathymetry: Bathymetry,
+}
+
+/// The shallows around the islands, where the waves shorten, steepen, turn towards the
+/// beach and break, and the calm they leave behind the islands.
+#[derive(Debug, Clone, Default)]
+pub struct Shore {
+    pub shoals: Vec<Shoal>,
+    /// m the water stands above the mean level the bathymetry is measured from
+    pub tide: f32,
+}
+
+/// Where a point lies against the nearest shoal at the current tide, the part of
+/// `ShorePoint::shoal` that is the same for every wave there. See `Shore::at`.
+#[derive(Debug, Clone, Copy)]
+pub struct ShorePoint {
+    /// the nearest shoal, raised by the tide
+    shoal: Shoal,
+    /// from the shoal's center
+    offset: Vec2,
+    distance: f32,
+    /// m of water, up to the open sea floor
+    depth: f32,
+}
+
+/// One wave as it is at a point, see `ShorePoint::shoal`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShoaledWave {
+    /// where the crests run, normalized
+    pub direction: Vec2,
+    pub k: f32,
+    pub amplitude: f32,
+    /// added to the phase the wave has out at sea
+    pub phase: f32,
+    /// from 0 to 1, how far past its breaking point the wave would be
+    pub breaking: f32,
+}
+
+impl Bathymetry {
+    /// m of water `distance` from the center, none on land.
+    pub fn depth_at(&self, distance: f32) -> f32 {
+        ((distance - self.radius) * self.slope).clamp(0., self.depth)
+    }
+
+    /// m from the center where the floor levels out into the open sea.
+    pub fn reach(&self) -> f32 {
+        self.radius + self.depth / self.slope
+    }
+
+    /// The same floor with the water `tide` m higher: deeper all over, and the beach
+    /// further up the slope.
+    pub fn raised(&self, tide: f32) -> Self {
+        Bathymetry {
+            radius: self.radius - tide / self.slope,
+            slope: self.slope,
+            depth: self.depth + tide,
+        }
+    }
+
+    /// Phase a wave of `k` out at sea has gained coming in from the open sea to `distance`
+    /// from the center, and how much its wave number has grown there.
+    fn gain(&self, k: f32, distance: f32) -> (f32, f32) {
+        if distance >= self.reach() {
+            return (0., 0.);
+        }
+        let depth = self.depth_at(distance);
+        let phase = (phase_gain(k * self.depth) - phase_gain(k * depth)) / self.slope;
+        // nothing more to gain on land
+        let grown = if distance > self.radius {
+            shallow_k(k, depth) - k
+        } else {
+            0.
+        };
+        (phase, grown)
+    }
+}
+
+/// Antiderivative of sqrt(coth u) - 1: how much phase a wave gains over deep water per
+/// unit of u = k * depth it climbs, with the wave number from `shallow_k`.
+fn phase_gain(u: f32) -> f32 {
+    let t = u.clamp(1e-4, DEEP).tanh().sqrt();
+    0.5 * ((1. + t) / (1. - t)).ln() + t.atan() - u.clamp(1e-4, DEEP)
+}
+
+/// Wave number in `depth` of a wave with `k` out at sea, Eckart's approximation of the
+/// dispersion relation.
+fn shallow_k(k: f32, depth: f32) -> f32 {
+    k / (k * depth).clamp(1e-4, DEEP).tanh().sqrt()
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
+
+impl ShoaledWave {
+    pub fn open_sea(direction: Vec2, k: f32, amplitude: f32) -> Self {
+        ShoaledWave {
+            direction,
+            k,
+            amplitude,
+            phase: 0.,
+            breaking: 0.,
+        }
+    }
+}
+
+impl Shoal {
+    /// How much of a wave is left `along` its way past the center and `across` it, from 1
+    /// in the open to 0 right behind the island.
+    fn lee(&self, along: f32, across: f32) -> f32 {
+        if along <= 0. {
+            return 1.;
+        }
+        let radius = self.bathymetry.radius;
+        let behind = 1. - smoothstep(radius, 2. * radius, across.abs());
+        let filled = (-along / (LEE_LENGTH * radius)).exp();
+        1. - behind * filled * smoothstep(0., radius, along)
+    }
+}
+
+impl Shore {
+    /// The shoal `point` is closest to the open sea edge of.
+    fn nearest(&self, point: Vec2) -> Option<&Shoal> {
+        self.shoals.iter().take(MAX_SHOALS).min_by(|a, b| {
+            let edge = |shoal: &Shoal| point.distance(shoal.center) - shoal.bathymetry.reach();
+            edge(a).total_cmp(&edge(b))
+        })
+    }
+
+    #[allow(dead_code)]
+    /// m of water at `point` at the current tide, infinite away from the islands.
+    pub fn depth_at(&self, point: Vec2) -> f32 {
+        match self.at(point) {
+            Some(at) if at.distance < at.shoal.bathymetry.reach() => at.depth,
+            _ => f32::INFINITY,
+        }
+    }
+
+    /// Where `point` lies against the nearest island, none without islands. Only that
+    /// island counts, they are never close together. Same as shore_point in water.wgsl,
+    /// keep both in sync.
+    pub fn at(&self, point: Vec2) -> Option<ShorePoint> {
+        let nearest = self.nearest(point)?;
+        let shoal = Shoal {
+            center: nearest.center,
+            bathymetry: nearest.bathymetry.raised(self.tide),
+        };
+        let offset = point - shoal.center;
+        let distance = offset.length().max(1e-3);
+        Some(ShorePoint {
+            shoal,
+            offset,
+            distance,
+            depth: shoal.bathymetry.depth_at(distance),
+        })
+    }
+}
+
+impl ShorePoint {
+    /// What the island does to `wave` here. Over the shallows the wave slows down, the
+    /// phase it gains on its way shortens it and turns its crests to face the shore, and
+    /// it piles up until it breaks at `BREAKER_INDEX`. Same as shoal_wave in water.wgsl,
+    /// keep both in sync.
+    pub fn shoal(&self, wave: ShoaledWave) -> ShoaledWave {
+        let (shoal, offset, distance) = (&self.shoal, self.offset, self.distance);
+        let bathymetry = &shoal.bathymetry;
+        let along = wave.direction.dot(offset);
+        let across = wave.direction.perp_dot(offset);
+        let lee = shoal.lee(along, across);
+        if distance >= bathymetry.reach() && along <= 0. {
+            return wave;
+        }
+
+        // The phase gained along the straight way in. Past the point closest to the
+        // center the wave climbs back out again, losing what it gains there, so it goes
+        // on smoothly and the waves that passed to either side bend in behind the island.
+        let (gained, grown) = bathymetry.gain(wave.k, distance);
+        let outwards = offset / distance;
+        let (phase, wave_vector) = if along <= 0. {
+            (gained, wave.k * wave.direction - grown * outwards)
+        } else {
+            let (closest, closest_grown) = bathymetry.gain(wave.k, across.abs());
+            let sideways = wave.direction.perp() * across.signum();
+            (
+                2. * closest - gained,
+                wave.k * wave.direction + grown * outwards - 2. * closest_grown * sideways,
+            )
+        };
+        let k = wave_vector.length();
+        let steepest = MAX_STEEPENING * wave.k * wave.amplitude / k;
+        if distance >= bathymetry.reach() {
+            return ShoaledWave {
+                direction: wave_vector / k,
+                k,
+                amplitude: (wave.amplitude * lee).min(steepest),
+                phase,
+                breaking: 0.,
+            };
+        }
+
+        // slower waves bunch up: the same energy flux at a lower group speed
+        let depth = self.depth;
+        let ku = (shallow_k(wave.k, depth) * depth).clamp(1e-4, 10.);
+        let group =
+            (wave.k * depth).clamp(1e-4, DEEP).tanh().sqrt() * (1. + 2. * ku / (2. * ku).sinh());
+        let shoaled = wave.amplitude * lee / group.sqrt();
+        let limit = (BREAKER_INDEX / 2. * depth).min(steepest);
+        ShoaledWave {
+            direction: wave_vector / k,
+            k,
+            amplitude: shoaled.min(limit),
+            phase,
+            breaking: if shoaled > limit {
+                (shoaled / limit.max(1e-6) - 1.).min(1.)
+            } else {
+                0.
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shore() -> Shore {
+        Shore {
+            shoals: vec![Shoal {
+                center: Vec2::ZERO,
+                bathymetry: Bathymetry {
+                    radius: 30.,
+                    slope: 0.05,
+                    depth: 25.,
+                },
+            }],
+            tide: 0.,
+        }
+    }
+
+    fn open() -> ShoaledWave {
+        ShoaledWave::open_sea(Vec2::X, 2. * std::f32::consts::PI / 40., 0.2)
+    }
+
+    fn shoal(point: Vec2) -> ShoaledWave {
+        shore().at(point).unwrap().shoal(open())
+    }
+
+    /// Coming in at an angle on the island's -z side, `x` m out along both axes.
+    fn coming_in(x: f32) -> ShoaledWave {
+        shoal(Vec2::new(-x, -x))
+    }
+
+    #[test]
+    fn open_sea_is_left_alone() {
+        assert_eq!(coming_in(1000.), open());
+        assert!(Shore::default().at(Vec2::ZERO).is_none());
+    }
+
+    #[test]
+    fn waves_shorten_towards_the_beach() {
+        let (far, near, surf) = (coming_in(300.), coming_in(40.), coming_in(25.));
+        assert!(open().k < far.k, "{} {}", open().k, far.k);
+        assert!(
+            far.k < near.k && near.k < surf.k,
+            "{} {} {}",
+            far.k,
+            near.k,
+            surf.k
+        );
+    }
+
+    #[test]
+    fn waves_turn_to_face_the_beach() {
+        // from +x towards the beach at +x +z
+        let (far, near) = (coming_in(300.), coming_in(40.));
+        assert!(far.direction.y > 0., "{:?}", far.direction);
+        assert!(near.direction.y > far.direction.y, "{:?}", near.direction);
+    }
+
+    #[test]
+    fn waves_pile_up_and_break_in_the_surf() {
+        let near = coming_in(40.);
+        assert!(near.amplitude > open().amplitude, "{}", near.amplitude);
+        assert_eq!(near.breaking, 0.);
+        let surf = coming_in(25.);
+        let depth = shore().depth_at(Vec2::splat(-25.));
+        assert!(surf.breaking > 0.);
+        assert!(
+            surf.amplitude <= BREAKER_INDEX / 2. * depth,
+            "{} {}",
+            surf.amplitude,
+            depth
+        );
+    }
+
+    #[test]
+    fn phase_goes_on_smoothly_into_the_shallows() {
+        let edge = 30. + 25. / 0.05;
+        let inside = shoal(Vec2::new(-edge + 0.5, 0.));
+        assert!(inside.phase.abs() < 0.01, "{}", inside.phase);
+    }
+
+    #[test]
+    fn calm_behind_the_island_only() {
+        let lee = shoal(Vec2::new(80., 0.));
+        let side = shoal(Vec2::new(80., 150.));
+        assert!(
+            lee.amplitude < 0.5 * side.amplitude,
+            "{} {}",
+            lee.amplitude,
+            side.amplitude
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use super::WaveProperties;
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f32::consts::{FRAC_PI_2, PI};
+
+pub const GRAVITY: f32 = 9.8;
+/// Stokes limit H/λ ≈ 1/7, expressed as k * a (our `WaveProperties::steepness`).
+pub const BREAKING_STEEPNESS: f32 = PI / 7.;
+/// Gerstner crests loop over themselves when the summed steepness reaches 1.
+const MAX_TOTAL_STEEPNESS: f32 = 0.9;
+/// Anything shorter is not resolved by the water mesh anyway.
+const MIN_WAVELENGTH: f32 = 1.;
+/// Sampled band around the spectral peak, as multiples of the peak frequency.
+const BAND: (f32, f32) = (0.6, 4.0);
+/// Mitsuyasu cos^2s(θ/2) spreading exponent for the PM and JONSWAP spectra.
+const SPREADING: i32 = 10;
+
+pub const OPEN_SEA_WIND_SPEED: f32 = 7.5;
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Spectrum {
+    /// Fully developed sea, wind has blown long enough over unlimited fetch.
+    PiersonMoskowitz,
+    /// Fetch limited, developing sea. `gamma` is the peak enhancement, 3.3 is typical.
+    Jonswap { gamma: f32 },
+    /// Tessendorf's spectrum, waves only travel downwind with cos² spreading.
+    Phillips,
+}
+
+/// Wind conditions that a set of waves is generated from.
+#[derive(Debug, Clone)]
+pub struct SeaState {
+    pub spectrum: Spectrum,
+    /// m/s, measured 10m above the surface
+    pub wind_speed: f32,
+    /// direction the wind blows towards, in world xz
+    pub wind_direction: Vec2,
+    /// distance in m the wind has blown over open water, only used by JONSWAP
+    pub fetch: f32,
+    pub seed: u64,
+    pub components: usize,
+}
+
+impl Default for SeaState {
+    fn default() -> Self {
+        SeaState {
+            spectrum: Spectrum::Jonswap { gamma: 3.3 },
+            wind_speed: OPEN_SEA_WIND_SPEED,
+            wind_direction: Vec2::new(1.0, 0.3),
+            fetch: 100_000.,
+            seed: 0,
+            components: 16,
+        }
+    }
+}
+
+impl SeaState {
+    /// Angular frequency (rad/s) where the spectrum has the most energy.
+    pub fn peak_frequency(&self) -> f32 {
+        let pierson_moskowitz = 0.877 * GRAVITY / self.wind_speed;
+        match self.spectrum {
+            Spectrum::PiersonMoskowitz | Spectrum::Phillips => pierson_moskowitz,
+            Spectrum::Jonswap { .. } => {
+                let developing =
+                    22. * (GRAVITY * GRAVITY / (self.wind_speed * self.fetch)).powf(1. / 3.);
+                // a long enough fetch turns into a fully developed sea
+                developing.max(pierson_moskowitz)
+            }
+        }
+    }
+
+    /// Omnidirectional spectral density S(ω) in m²s.
+    pub fn density(&self, omega: f32) -> f32 {
+        let g2 = GRAVITY * GRAVITY;
+        match self.spectrum {
+            Spectrum::PiersonMoskowitz => {
+                let omega_0 = GRAVITY / self.wind_speed;
+                8.1e-3 * g2 / omega.powi(5) * (-0.74 * (omega_0 / omega).powi(4)).exp()
+            }
+            Spectrum::Jonswap { gamma } => {
+                let omega_p = self.peak_frequency();
+                let alpha =
+                    0.076 * (self.wind_speed * self.wind_speed / (self.fetch * GRAVITY)).powf(0.22);
+                let sigma = if omega <= omega_p { 0.07 } else { 0.09 };
+                let r =
+                    (-(omega - omega_p).powi(2) / (2. * sigma * sigma * omega_p * omega_p)).exp();
+                alpha * g2 / omega.powi(5)
+                    * (-1.25 * (omega_p / omega).powi(4)).exp()
+                    * gamma.powf(r)
+            }
+            Spectrum::Phillips => {
+                // P(k) = A exp(-1 / (kL)²) / k⁴ with L = U² / g, moved over to ω
+                let omega_c = GRAVITY / self.wind_speed;
+                8.1e-3 * g2 / omega.powi(5) * (-(omega_c / omega).powi(4)).exp()
+            }
+        }
+    }
+
+    /// Relative weight of waves travelling at `theta` radians from the wind, not normalized.
+    fn spreading(&self, theta: f32) -> f32 {
+        match self.spectrum {
+            Spectrum::Phillips => {
+                if theta.abs() < FRAC_PI_2 {
+                    theta.cos().powi(2)
+                } else {
+                    0.
+                }
+            }
+            _ => (theta / 2.).cos().abs().powi(2 * SPREADING),
+        }
+    }
+
+    /// Spreading normalized to integrate to 1 over all directions, for 2D spectra.
+    pub fn directional_spreading(&self) -> impl Fn(f32) -> f32 + '_ {
+        const STEPS: usize = 256;
+        let step = 2. * PI / STEPS as f32;
+        let integral: f32 = (0..STEPS)
+            .map(|i| self.spreading(-PI + (i as f32 + 0.5) * step) * step)
+            .sum();
+        move |theta| self.spreading(theta) / integral
+    }
+
+    /// Samples the spectrum into `components` Gerstner waves, deterministic for a given seed.
+    pub fn waves(&self) -> Vec<WaveProperties> {
+        if self.wind_speed <= 0. || self.components == 0 {
+            return vec![];
+        }
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let wind_angle = self.wind_direction.y.atan2(self.wind_direction.x);
+
+        let omega_p = self.peak_frequency();
+        // the band stops short of the unresolved ripples, so a changing wind keeps the
+        // number of waves and every wave its random numbers
+        let omega_max = (BAND.1 * omega_p).min((2. * PI * GRAVITY / MIN_WAVELENGTH).sqrt());
+        let omega_min = BAND.0 * omega_p;
+        if omega_min >= omega_max {
+            return vec![];
+        }
+        // log spaced bins, so the short chop does not eat up all the components
+        let step = (omega_max / omega_min).ln() / self.components as f32;
+
+        let mut waves: Vec<WaveProperties> = (0..self.components)
+            .map(|i| {
+                let low = omega_min * (step * i as f32).exp();
+                let high = (omega_min * (step * (i + 1) as f32).exp()).min(omega_max);
+                let omega = rng.gen_range(low, high);
+                let theta = self.sample_direction(&mut rng);
+                let phase = rng.gen_range(0., 2. * PI);
+
+                let k = omega * omega / GRAVITY;
+                let amplitude = (2. * self.density(omega) * (high - low)).sqrt();
+                let angle = wind_angle + theta;
+                WaveProperties {
+                    wavelength: 2. * PI / k,
+                    steepness: (k * amplitude).min(BREAKING_STEEPNESS),
+                    direction: Vec2::new(angle.cos(), angle.sin()),
+                    phase,
+                }
+            })
+            .collect();
+
+        let total: f32 = waves.iter().map(|wave| wave.steepness).sum();
+        if total > MAX_TOTAL_STEEPNESS {
+            for wave in waves.iter_mut() {
+                wave.steepness *= MAX_TOTAL_STEEPNESS / total;
+            }
+        }
+        waves
+    }
+
+    fn sample_direction(&self, rng: &mut StdRng) -> f32 {
+        // rejection sampling, the spreading functions all peak at 1 downwind
+        loop {
+            let theta = rng.gen_range(-PI, PI);
+            if rng.gen::<f32>() <= self.spreading(theta) {
+                return theta;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spectra() -> [Spectrum; 3] {
+        [
+            Spectrum::PiersonMoskowitz,
+            Spectrum::Jonswap { gamma: 3.3 },
+            Spectrum::Phillips,
+        ]
+    }
+
+    #[test]
+    fn waves_stay_below_breaking() {
+        for spectrum in spectra() {
+            for wind_speed in [1., 5., 10., 20., 35.] {
+                for seed in 0..8 {
+                    let waves = SeaState {
+                        spectrum,
+                        wind_speed,
+                        seed,
+                        components: 64,
+                        ..Default::default()
+                    }
+                    .waves();
+                    let total: f32 = waves.iter().map(|wave| wave.steepness).sum();
+                    assert!(total <= 1., "{:?} {} m/s: {}", spectrum, wind_speed, total);
+                    for wave in waves {
+                        assert!(wave.steepness <= BREAKING_STEEPNESS, "{:?}", wave);
+                        assert!(wave.wavelength >= MIN_WAVELENGTH, "{:?}", wave);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_sea() {
+        let sea_state = SeaState {
+            seed: 42,
+            ..Default::default()
+        };
+        let a = sea_state.waves();
+        let b = sea_state.waves();
+        assert_eq!(a.len(), b.len());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_eq!(a.wavelength, b.wavelength);
+            assert_eq!(a.phase, b.phase);
+            assert_eq!(a.direction, b.direction);
+        }
+    }
+
+    #[test]
+    fn phillips_waves_travel_downwind() {
+        let sea_state = SeaState {
+            spectrum: Spectrum::Phillips,
+            wind_direction: Vec2::new(0., 1.),
+            components: 64,
+            ..Default::default()
+        };
+        for wave in sea_state.waves() {
+            assert!(
+                wave.direction.dot(sea_state.wind_direction) >= 0.,
+                "{:?}",
+                wave
+            );
+        }
+    }
+
+    #[test]
+    fn stronger_wind_longer_waves() {
+        for spectrum in spectra() {
+            let peak = |wind_speed| {
+                SeaState {
+                    spectrum,
+                    wind_speed,
+                    ..Default::default()
+                }
+                .peak_frequency()
+            };
+            assert!(peak(15.) < peak(5.), "{:?}", spectrum);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use super::{Water, WaveClock};
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+/// Rise and fall of the sea level. Semi-diurnal, two high and two low waters a day, with
+/// the day of the game squeezed into a few minutes so the beaches and reefs can be seen
+/// to drown and dry. It keeps to game time, the weather's wave speed does not hurry it.
+#[derive(Debug, Clone)]
+pub struct Tide {
+    /// m between low and high water
+    pub range: f32,
+    /// s of game time from one high water to the next
+    pub period: f32,
+    /// m the water stands above the mean level now
+    pub height: f32,
+}
+
+impl Default for Tide {
+    fn default() -> Self {
+        Tide {
+            range: 1.6,
+            period: 600.,
+            height: 0.,
+        }
+    }
+}
+
+impl Tide {
+    /// Height of the tide at the time of `clock`, starting out at high water.
+    pub fn at(&self, clock: &WaveClock) -> f32 {
+        self.range / 2. * clock.game_phase(TAU / self.period).cos()
+    }
+}
+
+/// Moves the water up and down with the tide, the islands' shoals stay where they are.
+pub fn tide_system(
+    clock: Res<WaveClock>,
+    mut tide: ResMut<Tide>,
+    mut water_query: Query<(&mut Water, &mut Transform)>,
+) {
+    tide.height = tide.at(&clock);
+    for (mut water, mut transform) in water_query.iter_mut() {
+        transform.translation.y = tide.height;
+        water.shore.tide = tide.height;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tide_turns_twice_a_day() {
+        let tide = Tide::default();
+        let mut clock = WaveClock::default();
+        assert_eq!(tide.at(&clock), tide.range / 2.);
+        clock.tick(tide.period / 2., 1.);
+        assert!((tide.at(&clock) + tide.range / 2.).abs() < 1e-4);
+        clock.tick(tide.period / 2., 1.);
+        assert!((tide.at(&clock) - tide.range / 2.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn tide_keeps_to_game_time() {
+        let tide = Tide::default();
+        let (mut calm, mut storm) = (WaveClock::default(), WaveClock::default());
+        calm.tick(tide.period / 4., 0.7);
+        storm.tick(tide.period / 4., 1.);
+        assert_eq!(tide.at(&calm), tide.at(&storm));
+        assert!(tide.at(&calm).abs() < 1e-4);
+
+        // waves stepped on their own do not move it
+        calm.step(tide.period);
+        assert_eq!(tide.at(&calm), tide.at(&storm));
+        // nor does the menu
+        storm.paused = true;
+        storm.tick(tide.period / 4., 1.);
+        assert_eq!(tide.at(&calm), tide.at(&storm));
+    }
+}
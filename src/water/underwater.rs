@@ -0,0 +1,364 @@
+use super::reflection::UNREFLECTED_LAYER;
+use super::{Water, WaterCamera, WaterSurface, WaveClock, SURFACE_TOLERANCE};
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::render::mesh::MeshVertexBufferLayout;
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError,
+};
+use bevy::render::view::RenderLayers;
+use std::f32::consts::PI;
+
+/// m of water around the waterline over which the camera goes from above to below.
+const WATERLINE: f32 = 0.4;
+/// Seconds for the view to settle after crossing the waterline.
+const TRANSITION: f32 = 0.25;
+/// m of water it takes to fade things to a third, horizontally.
+const VISIBILITY: f32 = 12.;
+/// m of water it takes for the light to drop to a third, from the surface down.
+const LIGHT_DEPTH: f32 = 10.;
+/// m from the camera of the shells the fog is layered with, the last hides everything.
+const FOG_SHELLS: [f32; 7] = [1.5, 3., 6., 12., 24., 48., 96.];
+/// m from the camera of the shell that darkens the view by the light lost in the water,
+/// inside all the fog shells.
+const DIM_SHELL: f32 = 0.5;
+/// m between god rays, each cell of a grid around the camera has one.
+const RAY_SPACING: f32 = 6.;
+/// Rays along each side of the grid.
+const RAY_GRID: i32 = 5;
+const RAY_LENGTH: f32 = 30.;
+
+/// How far the camera is under water, found from the same wave query floating uses.
+#[derive(Debug, Default)]
+pub struct Underwater {
+    /// m below the surface, negative above
+    pub depth: f32,
+    /// 0 above water up to 1 below, eased in and out
+    pub amount: f32,
+}
+
+impl Underwater {
+    /// Follows the camera to `depth` m below the surface, `delta` seconds on.
+    pub fn update(&mut self, depth: f32, delta: f32) {
+        self.depth = depth;
+        let target = ((depth + WATERLINE / 2.) / WATERLINE).clamp(0., 1.);
+        let ease = 1. - (-delta / TRANSITION).exp();
+        self.amount += (target - self.amount) * ease;
+    }
+
+    pub fn submerged(&self) -> bool {
+        self.amount > 0.001
+    }
+
+    /// Share of the daylight that reaches the camera, the deeper the darker on top of
+    /// the light lost at the surface.
+    pub fn light(&self) -> f32 {
+        1. - self.amount * (1. - 0.5 * (-self.depth.max(0.) / LIGHT_DEPTH).exp())
+    }
+}
+
+#[derive(AsBindGroup, TypeUuid, Clone)]
+#[uuid = "2687a3bf-36bd-47ed-91b0-4fa2290f2bc1"]
+pub struct GodRayMaterial {
+    #[uniform(0)]
+    pub color: Vec4,
+    #[uniform(1)]
+    pub time: f32,
+    #[uniform(2)]
+    pub amount: f32,
+}
+
+#[derive(Component)]
+pub struct FogShell;
+
+/// Black, and as opaque as the water has taken light away. Blended over everything
+/// behind it, it dims the view without touching the scene's lights. It is the innermost
+/// `FogShell` too, following the camera with the others.
+#[derive(Component)]
+pub struct DimShell;
+
+#[derive(Component)]
+pub struct GodRay {
+    /// cell of the ray grid around the camera cell
+    cell: IVec2,
+}
+
+impl Material for GodRayMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/underwater.wgsl".into()
+    }
+    fn fragment_shader() -> ShaderRef {
+        "shaders/underwater.wgsl".into()
+    }
+    fn alpha_mode(&self) -> AlphaMode {
+        AlphaMode::Blend
+    }
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        _layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        descriptor.fragment.as_mut().unwrap().entry_point = "god_ray".into();
+        descriptor.primitive.cull_mode = None;
+        Ok(())
+    }
+}
+
+pub fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut god_ray_materials: ResMut<Assets<GodRayMaterial>>,
+) {
+    let mut inside = 0.;
+    for radius in FOG_SHELLS {
+        // each shell hides what the water between it and the last one would
+        let alpha = 1. - (-(radius - inside) / VISIBILITY).exp();
+        inside = radius;
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: meshes.add(Mesh::from(shape::Icosphere {
+                    radius,
+                    subdivisions: 3,
+                })),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::rgba(0., 0., 0., alpha),
+                    unlit: true,
+                    cull_mode: None,
+                    alpha_mode: AlphaMode::Blend,
+                    ..Default::default()
+                }),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(FogShell)
+            .insert(RenderLayers::layer(UNREFLECTED_LAYER))
+            .insert(Name::new("Underwater-fog"));
+    }
+    commands
+        .spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Icosphere {
+                radius: DIM_SHELL,
+                subdivisions: 3,
+            })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::NONE,
+                unlit: true,
+                cull_mode: None,
+                alpha_mode: AlphaMode::Blend,
+                ..Default::default()
+            }),
+            visibility: Visibility { is_visible: false },
+            ..Default::default()
+        })
+        .insert(FogShell)
+        .insert(DimShell)
+        .insert(RenderLayers::layer(UNREFLECTED_LAYER))
+        .insert(Name::new("Underwater-dim"));
+
+    let ray_material = god_ray_materials.add(GodRayMaterial {
+        color: Vec4::ONE,
+        time: 0.,
+        amount: 0.,
+    });
+    let ray_mesh = meshes.add(Mesh::from(shape::Quad::new(Vec2::new(1.5, RAY_LENGTH))));
+    for x in -RAY_GRID / 2..=RAY_GRID / 2 {
+        for z in -RAY_GRID / 2..=RAY_GRID / 2 {
+            commands
+                .spawn_bundle(MaterialMeshBundle {
+                    mesh: ray_mesh.clone(),
+                    material: ray_material.clone(),
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(RenderLayers::layer(UNREFLECTED_LAYER))
+                .insert(GodRay {
+                    cell: IVec2::new(x, z),
+                })
+                .insert(Name::new("Underwater-ray"));
+        }
+    }
+}
+
+/// Same pseudo random jitter for a world cell every time.
+fn cell_jitter(cell: IVec2) -> Vec2 {
+    let n = (cell.x as f32 * 12.9898 + cell.y as f32 * 78.233).sin() * 43758.547;
+    Vec2::new(n.fract(), (n * 1.618).fract()).abs() - 0.5
+}
+
+/// Fog shells, kept around the camera.
+type ShellQuery<'w, 's> = Query<
+    'w,
+    's,
+    (&'static mut Transform, &'static mut Visibility),
+    (With<FogShell>, Without<WaterCamera>),
+>;
+
+/// Follows the camera under the surface, waves, wakes and ripples and all.
+pub fn underwater_system(
+    time: Res<Time>,
+    surface: WaterSurface,
+    mut underwater: ResMut<Underwater>,
+    water_query: Query<(&Water, &GlobalTransform)>,
+    camera_query: Query<&Transform, With<WaterCamera>>,
+) {
+    let (water, water_transform) = match water_query.get_single() {
+        Ok(water) => water,
+        Err(_) => return,
+    };
+    let camera = match camera_query.get_single() {
+        Ok(transform) => transform.translation,
+        Err(_) => return,
+    };
+
+    let point = Vec2::new(camera.x, camera.z);
+    let height = water_transform.translation().y
+        + water
+            .surface_at_point(point, &surface.clock, SURFACE_TOLERANCE)
+            .position
+            .y
+        + surface.wake.at(point).height
+        + surface.ripples.at(point).0;
+    underwater.update(height - camera.y, time.delta_seconds());
+}
+
+/// Wraps the camera in the fog shells while it is under water.
+pub fn fog_system(
+    underwater: Res<Underwater>,
+    water_query: Query<&Water>,
+    camera_query: Query<&Transform, With<WaterCamera>>,
+    mut shell_query: ShellQuery,
+    shell_material_query: Query<&Handle<StandardMaterial>, (With<FogShell>, Without<DimShell>)>,
+    dim_material_query: Query<&Handle<StandardMaterial>, With<DimShell>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let (water, camera) = match (water_query.get_single(), camera_query.get_single()) {
+        (Ok(water), Ok(transform)) => (water, transform.translation),
+        _ => return,
+    };
+
+    // the dim shell in front darkens the fog and the rays along with everything else
+    let fog = water.color * 0.3;
+    for (mut transform, mut visibility) in shell_query.iter_mut() {
+        visibility.is_visible = underwater.submerged();
+        transform.translation = camera;
+    }
+    for handle in shell_material_query.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            let alpha = material.base_color.a();
+            material.base_color = fog;
+            material.base_color.set_a(alpha);
+        }
+    }
+    for handle in dim_material_query.iter() {
+        if let Some(material) = materials.get_mut(handle) {
+            material.base_color = Color::rgba(0., 0., 0., 1. - underwater.light());
+        }
+    }
+}
+
+/// Slants the god rays around the camera along the sunlight while it is under water.
+pub fn god_ray_system(
+    clock: Res<WaveClock>,
+    underwater: Res<Underwater>,
+    light_query: Query<&GlobalTransform, With<DirectionalLight>>,
+    water_query: Query<(&Water, &GlobalTransform)>,
+    camera_query: Query<&Transform, With<WaterCamera>>,
+    mut ray_query: Query<(&GodRay, &mut Transform, &mut Visibility), Without<WaterCamera>>,
+    mut god_ray_materials: ResMut<Assets<GodRayMaterial>>,
+) {
+    let ((water, water_transform), camera) =
+        match (water_query.get_single(), camera_query.get_single()) {
+            (Ok(water), Ok(transform)) => (water, transform.translation),
+            _ => return,
+        };
+
+    // light bends towards straight down as it enters the water
+    let sun = light_query
+        .iter()
+        .next()
+        .map_or(-Vec3::Y, |transform| transform.forward());
+    let ray = Vec3::new(sun.x / 1.33, sun.y.min(-0.1), sun.z / 1.33).normalize();
+    let camera_cell = (Vec2::new(camera.x, camera.z) / RAY_SPACING)
+        .floor()
+        .as_ivec2();
+    for (god_ray, mut transform, mut visibility) in ray_query.iter_mut() {
+        visibility.is_visible = underwater.submerged();
+        let cell = camera_cell + god_ray.cell;
+        let top = (cell.as_vec2() + 0.5 + cell_jitter(cell)) * RAY_SPACING;
+        let top = Vec3::new(top.x, water_transform.translation().y, top.y);
+        let center = top + ray * RAY_LENGTH / 2.;
+        // turned around its length to face the camera
+        let up = -ray;
+        let right = up.cross(camera - center).normalize_or_zero();
+        if right == Vec3::ZERO {
+            continue;
+        }
+        transform.translation = center;
+        transform.rotation = Quat::from_mat3(&Mat3::from_cols(right, up, right.cross(up)));
+    }
+
+    for (_, material) in god_ray_materials.iter_mut() {
+        // the flicker runs at 1.3 rad/s, wrapped to one of its periods
+        material.time = clock.wrapped(2. * PI / 1.3);
+        material.amount = underwater.amount;
+        material.color = water.color.into();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: f32 = 1. / 60.;
+
+    fn settled(depth: f32) -> Underwater {
+        let mut underwater = Underwater::default();
+        for _ in 0..120 {
+            underwater.update(depth, FRAME);
+        }
+        underwater
+    }
+
+    #[test]
+    fn submerged_below_the_waterline_only() {
+        assert!(!settled(-1.).submerged());
+        assert!(!settled(-WATERLINE).submerged());
+        assert!(settled(WATERLINE).submerged());
+        assert!((settled(2.).amount - 1.).abs() < 1e-3);
+        assert!((settled(-2.).light() - 1.).abs() < 1e-6);
+        // darker the deeper
+        assert!(settled(0.5).light() > settled(10.).light());
+        assert!(settled(10.).light() > 0.);
+    }
+
+    #[test]
+    fn crossing_the_waterline_fades_in_and_out() {
+        let mut underwater = Underwater::default();
+        let most = 1. - (-FRAME / TRANSITION).exp();
+        // down through the surface at 1 m/s, then back up
+        let depths = (0..240)
+            .map(|i| i as f32 * FRAME - 2.)
+            .chain((0..240).map(|i| 2. - i as f32 * FRAME));
+        let mut deepest: f32 = 0.;
+        for depth in depths {
+            let (amount, light) = (underwater.amount, underwater.light());
+            underwater.update(depth, FRAME);
+            // eased a little of the way towards the waterline's share each frame
+            let target = ((depth + WATERLINE / 2.) / WATERLINE).clamp(0., 1.);
+            let (low, high) = (amount.min(target), amount.max(target));
+            assert!(
+                low <= underwater.amount && underwater.amount <= high,
+                "{}",
+                depth
+            );
+            assert!((underwater.amount - amount).abs() <= most * (target - amount).abs() + 1e-6);
+            assert!((underwater.light() - light).abs() < 0.05, "{}", depth);
+            deepest = deepest.max(underwater.amount);
+        }
+        assert!(deepest > 0.999, "{}", deepest);
+        assert!(!underwater.submerged());
+    }
+}
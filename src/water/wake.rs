@@ -0,0 +1,199 @@
+use super::spectrum::GRAVITY;
+use super::{Current, WaveClock, WaveData};
+use bevy::prelude::*;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+/// Sources alive at once, the water shader's wake array has this size.
+pub const MAX_WAKE_SOURCES: usize = 32;
+/// Seconds between two sources dropped by a moving boat.
+const EMIT_INTERVAL: f32 = 0.5;
+/// m/s through the water below which a boat leaves no wake.
+const MIN_SPEED: f32 = 0.5;
+/// Seconds for a ring to fade to a third.
+const FADE: f32 = 8.;
+/// Seconds the churned up water right behind the hull stays white.
+const CHURN: f32 = 2.;
+/// Highest wave steepness a wake ring starts with.
+const STEEPNESS: f32 = 0.1;
+
+/// A disturbance a boat left in the water at some point of its path. It spreads out
+/// as a ring at a third of the boat's speed: the rings of all the points along the path
+/// then line up in the two arms of a Kelvin wake, 19.5° to either side of the path.
+#[derive(Debug, Clone)]
+pub struct WakeSource {
+    pub position: Vec2,
+    /// m/s of the boat when it passed here
+    pub speed: f32,
+    /// m, beam of the hull
+    pub width: f32,
+    /// seconds since the boat passed
+    pub age: f32,
+}
+
+/// The ring of waves a source has spread into by now.
+#[derive(Debug, Clone, Copy)]
+pub struct WakeRing {
+    pub center: Vec2,
+    pub radius: f32,
+    pub amplitude: f32,
+    /// wave number, the waves keep pace with the boat that made them
+    pub k: f32,
+    /// m from the radius where the ring has dropped to a third
+    pub packet: f32,
+    /// whiteness of the water right where the hull passed, from 0 to 1
+    pub churn: f32,
+    pub width: f32,
+}
+
+/// What the wake adds to the waves at a point.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WakeData {
+    pub height: f32,
+    /// (dh/dx, dh/dz)
+    pub slope: Vec2,
+    /// m/s the water at the surface is pushed along horizontally
+    pub velocity: Vec2,
+    pub churn: f32,
+}
+
+/// Wakes of everything moving through the water, oldest sources first.
+#[derive(Debug, Default)]
+pub struct Wake {
+    pub sources: VecDeque<WakeSource>,
+    /// seconds since the last source was dropped
+    since_emit: f32,
+    /// wave time of the last update
+    last: f64,
+}
+
+impl WakeSource {
+    pub fn ring(&self) -> WakeRing {
+        let k = GRAVITY / (self.speed * self.speed);
+        let radius = self.speed / 3. * self.age;
+        // a bigger hull pushes more water aside, but the wave can only get so steep
+        let amplitude = (0.05 * self.width).min(STEEPNESS / k)
+            * (-self.age / FADE).exp()
+            * (self.width / (self.width + radius)).sqrt();
+        WakeRing {
+            center: self.position,
+            radius,
+            amplitude,
+            k,
+            packet: PI / (2. * k) + self.width / 4.,
+            churn: (-self.age / CHURN).exp(),
+            width: self.width,
+        }
+    }
+}
+
+impl WakeRing {
+    /// Same as wake_ring in water.wgsl, keep both in sync.
+    fn at(&self, point: Vec2) -> WakeData {
+        let offset = point - self.center;
+        let distance = offset.length();
+        let x = distance - self.radius;
+        let mut data = WakeData {
+            churn: self.churn * (-(distance / self.width).powi(2)).exp(),
+            ..Default::default()
+        };
+        if x.abs() > 3. * self.packet || distance == 0. {
+            return data;
+        }
+        let envelope = self.amplitude * (-(x / self.packet).powi(2)).exp();
+        let (sin, cos) = (self.k * x).sin_cos();
+        let outwards = offset / distance;
+        let d_height = envelope * (-2. * x / (self.packet * self.packet) * cos - self.k * sin);
+        data.height = envelope * cos;
+        data.slope = outwards * d_height;
+        // water under a crest moves with the wave, under a trough against it
+        data.velocity = outwards * (GRAVITY * self.k).sqrt() * envelope * cos;
+        data
+    }
+}
+
+impl Wake {
+    /// Called every frame by the boat going at `speed` through the water, drops a source
+    /// every `EMIT_INTERVAL`.
+    pub fn emit(&mut self, position: Vec2, speed: f32, width: f32, delta: f32) {
+        self.since_emit += delta;
+        if speed < MIN_SPEED || self.since_emit < EMIT_INTERVAL {
+            return;
+        }
+        self.since_emit = 0.;
+        if self.sources.len() == MAX_WAKE_SOURCES {
+            self.sources.pop_front();
+        }
+        self.sources.push_back(WakeSource {
+            position,
+            speed,
+            width,
+            age: 0.,
+        });
+    }
+
+    /// Ages the sources to the time of `clock` and carries them along with the current.
+    pub fn update(&mut self, current: &Current, clock: &WaveClock) {
+        let delta = clock.since(&mut self.last);
+        for source in self.sources.iter_mut() {
+            source.age += delta;
+            source.position += current.at(source.position) * delta;
+        }
+        // nothing left to see after a few fades
+        while matches!(self.sources.front(), Some(source) if source.age > 4. * FADE) {
+            self.sources.pop_front();
+        }
+    }
+
+    pub fn at(&self, point: Vec2) -> WakeData {
+        self.sources
+            .iter()
+            .map(|source| source.ring().at(point))
+            .fold(WakeData::default(), |sum, ring| WakeData {
+                height: sum.height + ring.height,
+                slope: sum.slope + ring.slope,
+                velocity: sum.velocity + ring.velocity,
+                churn: sum.churn.max(ring.churn),
+            })
+    }
+}
+
+impl WakeData {
+    /// `surface` with the wake on top of it.
+    pub fn apply(&self, surface: &WaveData) -> WaveData {
+        surface.lifted(self.height, self.slope)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wake_spreads_in_a_kelvin_wedge() {
+        let mut wake = Wake::default();
+        let speed = 4.;
+        let current = Current::default();
+        let mut clock = WaveClock::default();
+        // a boat going straight along +x, it is at x = 0 at the end
+        let frames = 600;
+        let delta = 1. / 30.;
+        for frame in 0..frames {
+            let x = (frame - frames) as f32 * delta * speed;
+            wake.emit(Vec2::new(x, 0.), speed, 3., delta);
+            clock.step(delta);
+            wake.update(&current, &clock);
+        }
+        let strength = |x: f32, z: f32| {
+            (0..20)
+                .map(|i| wake.at(Vec2::new(x + i as f32 * 0.3, z)).height.abs())
+                .fold(0., f32::max)
+        };
+        // 19.5° off the path, 40 m behind the boat
+        let arm = 40. * (19.5f32).to_radians().tan();
+        assert!(strength(-40., arm) > 3. * strength(-40., 2.5 * arm));
+        assert!(strength(-40., 2.5 * arm) < 0.001);
+        // nothing ahead of the boat
+        assert!(strength(20., 0.) < 0.001);
+    }
+}
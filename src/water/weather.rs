@@ -0,0 +1,221 @@
+use super::clock::WaveClock;
+use super::spectrum::{SeaState, OPEN_SEA_WIND_SPEED};
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Seconds a change of weather takes to blow in.
+const TURNING_TIME: f32 = 45.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeatherState {
+    Calm,
+    Breeze,
+    Squall,
+    Storm,
+}
+
+/// What the sea looks like in one weather state.
+struct Conditions {
+    /// m/s
+    wind_speed: f32,
+    wave_speed: f32,
+    color: Color,
+    /// raindrops per m² and second
+    rain: f32,
+    /// seconds the state lasts, picked at random in this range
+    duration: (f32, f32),
+}
+
+impl WeatherState {
+    fn conditions(self) -> Conditions {
+        match self {
+            WeatherState::Calm => Conditions {
+                wind_speed: 2.,
+                wave_speed: 0.7,
+                color: Color::rgb(0.2, 0.6, 0.45),
+                rain: 0.,
+                duration: (120., 300.),
+            },
+            WeatherState::Breeze => Conditions {
+                wind_speed: OPEN_SEA_WIND_SPEED,
+                wave_speed: 0.8,
+                color: Color::SEA_GREEN,
+                rain: 0.,
+                duration: (180., 480.),
+            },
+            WeatherState::Squall => Conditions {
+                wind_speed: 13.,
+                wave_speed: 0.9,
+                color: Color::rgb(0.15, 0.38, 0.32),
+                rain: 20.,
+                duration: (60., 180.),
+            },
+            WeatherState::Storm => Conditions {
+                wind_speed: 20.,
+                wave_speed: 1.,
+                color: Color::rgb(0.1, 0.22, 0.22),
+                rain: 60.,
+                duration: (90., 240.),
+            },
+        }
+    }
+
+    /// Weather only ever turns one step calmer or rougher.
+    fn next(self, rng: &mut StdRng) -> Self {
+        let roll: f32 = rng.gen();
+        match self {
+            WeatherState::Calm => WeatherState::Breeze,
+            WeatherState::Breeze if roll < 0.5 => WeatherState::Calm,
+            WeatherState::Breeze => WeatherState::Squall,
+            WeatherState::Squall if roll < 0.6 => WeatherState::Breeze,
+            WeatherState::Squall => WeatherState::Storm,
+            WeatherState::Storm => WeatherState::Squall,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeatherEvent {
+    /// The weather starts turning, the sea takes `TURNING_TIME` to follow.
+    Turning {
+        from: WeatherState,
+        to: WeatherState,
+    },
+    /// The sea has fully become this state.
+    Settled(WeatherState),
+}
+
+pub struct Weather {
+    /// the sea right now, blended between states while the weather is turning
+    pub sea_state: SeaState,
+    pub state: WeatherState,
+    /// state the weather is turning to and how far it got, from 0 to 1
+    pub turning: Option<(WeatherState, f32)>,
+    /// seconds until the weather turns again
+    pub remaining: f32,
+    /// 0 on the open sea up to 1 where an island takes all the wind away
+    pub shelter: f32,
+    pub wave_speed: f32,
+    pub color: Color,
+    /// raindrops per m² and second
+    pub rain: f32,
+    /// wave time of the last update
+    last: f64,
+    rng: StdRng,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Weather::new(WeatherState::Breeze, 0)
+    }
+}
+
+impl Weather {
+    pub fn new(state: WeatherState, seed: u64) -> Self {
+        let conditions = state.conditions();
+        let mut rng = StdRng::seed_from_u64(seed);
+        Weather {
+            sea_state: SeaState {
+                wind_speed: conditions.wind_speed,
+                ..Default::default()
+            },
+            state,
+            turning: None,
+            remaining: rng.gen_range(conditions.duration.0, conditions.duration.1),
+            shelter: 0.,
+            wave_speed: conditions.wave_speed,
+            color: conditions.color,
+            rain: conditions.rain,
+            last: 0.,
+            rng,
+        }
+    }
+
+    /// Advances the weather to the time of `clock`, returns what changed if anything.
+    pub fn update(&mut self, clock: &WaveClock) -> Option<WeatherEvent> {
+        let delta = clock.since(&mut self.last);
+        let mut event = None;
+        match self.turning {
+            None => {
+                self.remaining -= delta;
+                if self.remaining <= 0. {
+                    let to = self.state.next(&mut self.rng);
+                    self.turning = Some((to, 0.));
+                    event = Some(WeatherEvent::Turning {
+                        from: self.state,
+                        to,
+                    });
+                }
+            }
+            Some((to, progress)) => {
+                let progress = progress + delta / TURNING_TIME;
+                if progress >= 1. {
+                    let duration = to.conditions().duration;
+                    self.state = to;
+                    self.turning = None;
+                    self.remaining = self.rng.gen_range(duration.0, duration.1);
+                    event = Some(WeatherEvent::Settled(to));
+                } else {
+                    self.turning = Some((to, progress));
+                }
+            }
+        }
+
+        let from = self.state.conditions();
+        let (to, t) = match self.turning {
+            Some((to, progress)) => (to.conditions(), progress * progress * (3. - 2. * progress)),
+            None => (self.state.conditions(), 0.),
+        };
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        self.sea_state.wind_speed = lerp(from.wind_speed, to.wind_speed) * (1. - self.shelter);
+        self.wave_speed = lerp(from.wave_speed, to.wave_speed);
+        self.rain = lerp(from.rain, to.rain);
+        self.color = Color::rgb(
+            lerp(from.color.r(), to.color.r()),
+            lerp(from.color.g(), to.color.g()),
+            lerp(from.color.b(), to.color.b()),
+        );
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weather_turns_smoothly_and_reports_it() {
+        let mut weather = Weather::new(WeatherState::Calm, 3);
+        let delta = 0.1;
+        let mut clock = WaveClock::default();
+        let mut turning = None;
+        let mut settled = 0;
+        for _ in 0..200_000 {
+            let wind_speed = weather.sea_state.wind_speed;
+            clock.step(delta);
+            match weather.update(&clock) {
+                Some(WeatherEvent::Turning { from, to }) => {
+                    assert_eq!(from, weather.state);
+                    assert_ne!(from, to);
+                    assert!(turning.is_none());
+                    turning = Some(to);
+                }
+                Some(WeatherEvent::Settled(state)) => {
+                    assert_eq!(turning.take(), Some(state));
+                    assert_eq!(weather.state, state);
+                    settled += 1;
+                }
+                None => {}
+            }
+            // the worst case is calm to breeze, or storm to squall, at mid transition
+            let most = 1.5 * 7. / TURNING_TIME * delta;
+            assert!(
+                (weather.sea_state.wind_speed - wind_speed).abs() <= most,
+                "{} -> {}",
+                wind_speed,
+                weather.sea_state.wind_speed
+            );
+        }
+        assert!(settled > 10, "{}", settled);
+    }
+}
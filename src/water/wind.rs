@@ -0,0 +1,139 @@
+use super::clock::WaveClock;
+use super::spectrum::SeaState;
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+/// rad the mean wind wanders off its starting direction at most
+const VEER: f32 = 0.5;
+/// rad the gusts turn the wind locally, at full gustiness
+const GUST_VEER: f32 = 0.3;
+/// rad per m downwind of the gust fronts' swings
+const GUST_FRONTS: [f32; 3] = [0.071, 0.19, 0.05];
+
+/// Wind over the sea. The mean speed follows the weather, the direction wanders slowly
+/// and gusts roll downwind with it, so two places or two moments never blow quite alike.
+pub struct Wind {
+    /// mean direction the wind blows towards, normalized
+    pub direction: Vec2,
+    /// mean speed in m/s, 10m above the surface
+    pub speed: f32,
+    /// 0 is a steady wind, 1 gusts from nothing up to twice the mean speed
+    pub gustiness: f32,
+    base_angle: f32,
+    /// how far each of `GUST_FRONTS` has rolled downwind, as a phase
+    fronts: [f32; 3],
+    /// wave time of the last update
+    last: f64,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        let sea_state = SeaState::default();
+        Wind::new(sea_state.wind_direction, sea_state.wind_speed)
+    }
+}
+
+impl Wind {
+    pub fn new(direction: Vec2, speed: f32) -> Self {
+        let direction = direction.normalize();
+        Wind {
+            direction,
+            speed,
+            gustiness: 0.3,
+            base_angle: direction.y.atan2(direction.x),
+            fronts: [0.; 3],
+            last: 0.,
+        }
+    }
+
+    /// Advances the wind to the time of `clock` with the weather now blowing at `speed`.
+    pub fn update(&mut self, speed: f32, clock: &WaveClock) {
+        let delta = clock.since(&mut self.last);
+        self.speed = speed;
+        for (front, k) in self.fronts.iter_mut().zip(GUST_FRONTS) {
+            *front = (*front + k * speed * delta).rem_euclid(TAU);
+        }
+        // a few incommensurate swings, repeats only after hours. All start at zero, so
+        // the wind does not jump on the first update
+        let wander = clock.phase(0.0107).sin() * 0.6
+            + clock.phase(0.0291).sin() * 0.3
+            + clock.phase(0.073).sin() * 0.1;
+        let angle = self.base_angle + VEER * wander;
+        self.direction = Vec2::new(angle.cos(), angle.sin());
+    }
+
+    /// Velocity of the wind at `point` in world xz, in m/s.
+    pub fn at(&self, point: Vec2) -> Vec2 {
+        let across = self.direction.perp();
+        // gust fronts are long across the wind and travel with it
+        let along = self.direction.dot(point);
+        let side = across.dot(point);
+        let [a, b, c] = GUST_FRONTS;
+        let [front_a, front_b, front_c] = self.fronts;
+        let gust = (along * a - front_a + (side * 0.013).sin() * 2.).sin() * 0.6
+            + (along * b - front_b + side * 0.047 + 2.).sin() * 0.4;
+        let turn = GUST_VEER * self.gustiness * (side * 0.031 + along * c - front_c).sin();
+
+        let speed = (self.speed * (1. + self.gustiness * gust)).max(0.);
+        Vec2::from_angle(turn).rotate(self.direction) * speed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points() -> impl Iterator<Item = Vec2> {
+        (0..400).map(|i| Vec2::new((i % 20) as f32 * 37. - 350., (i / 20) as f32 * 53. - 500.))
+    }
+
+    #[test]
+    fn gusts_stay_within_gustiness() {
+        let mut wind = Wind::new(Vec2::new(1., 0.3), 10.);
+        let mut clock = WaveClock::default();
+        for _ in 0..200 {
+            clock.step(0.7);
+            wind.update(10., &clock);
+            for point in points() {
+                let gust = wind.at(point);
+                let speed = gust.length();
+                assert!(speed <= 10. * (1. + wind.gustiness) + 1e-4, "{}", speed);
+                let turn = wind.direction.angle_between(gust).abs();
+                assert!(turn <= GUST_VEER * wind.gustiness + 1e-4, "{}", turn);
+            }
+        }
+    }
+
+    #[test]
+    fn wind_veers_slowly_and_not_far() {
+        let start = Vec2::new(1., 0.3).normalize();
+        let mut wind = Wind::new(start, 10.);
+        let mut direction = wind.direction;
+        let mut clock = WaveClock::default();
+        // a few hours, in seconds
+        for _ in 0..20000 {
+            clock.step(1.);
+            wind.update(10., &clock);
+            let veer = direction.angle_between(wind.direction).abs();
+            assert!(veer < 0.012, "{} rad in a second", veer);
+            assert!(start.angle_between(wind.direction).abs() <= VEER + 1e-4);
+            direction = wind.direction;
+        }
+    }
+
+    #[test]
+    fn same_time_same_wind() {
+        let mut a = Wind::new(Vec2::new(1., 0.3), 10.);
+        let mut b = Wind::new(Vec2::new(1., 0.3), 10.);
+        let mut clock = WaveClock::default();
+        for _ in 0..100 {
+            clock.step(0.25);
+            a.update(8., &clock);
+        }
+        b.update(8., &clock);
+        assert!((a.direction - b.direction).length() < 1e-4);
+        for point in points() {
+            assert!((a.at(point) - b.at(point)).length() < 1e-3, "{:?}", point);
+        }
+    }
+}